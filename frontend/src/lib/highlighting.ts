@@ -1,10 +1,13 @@
 import "highlight.js/styles/default.css";
 import "highlight.js/styles/an-old-hope.css";
 import hljs from "highlight.js/lib/core";
+import haskell from "highlight.js/lib/languages/haskell";
+import java from "highlight.js/lib/languages/java";
 import javascript from "highlight.js/lib/languages/javascript";
 import plaintext from "highlight.js/lib/languages/plaintext";
 import python from "highlight.js/lib/languages/python";
 import rust from "highlight.js/lib/languages/rust";
+import typescript from "highlight.js/lib/languages/typescript";
 
 hljs.registerLanguage("javascript", javascript);
 hljs.registerLanguage("js", javascript);
@@ -12,8 +15,22 @@ hljs.registerLanguage("python", python);
 hljs.registerLanguage("py", python);
 hljs.registerLanguage("rust", rust);
 hljs.registerLanguage("rs", rust);
+hljs.registerLanguage("java", java);
+hljs.registerLanguage("typescript", typescript);
+hljs.registerLanguage("ts", typescript);
+hljs.registerLanguage("haskell", haskell);
+hljs.registerLanguage("hs", haskell);
+hljs.registerLanguage("plaintext", plaintext);
 hljs.registerLanguage("example", plaintext);
 
+export const highlightSource = (block: HTMLElement) => {
+    if (!hljs.getLanguage(block.dataset.language ?? "")) {
+        block.dataset.language = "plaintext";
+    }
+    block.classList.add(`language-${block.dataset.language}`);
+    hljs.highlightElement(block);
+};
+
 const makePreCode = (text: string): HTMLPreElement => {
     const pre = document.createElement("pre");
     const code = document.createElement("code");
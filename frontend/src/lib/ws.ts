@@ -6,13 +6,61 @@ export type WebSocketRequest =
           program: string;
           language: string;
       }
+    | {
+          type: "samples";
+          program: string;
+          language: string;
+      }
     | {
           type: "test";
           program: string;
           language: string;
           input: string;
+      }
+    | {
+          type: "cancel";
       };
 
+export type Verdict =
+    | "AC"
+    | "WA"
+    | "TLE"
+    | "MLE"
+    | "RE"
+    | "CE"
+    | "CTLE"
+    | "OLE"
+    | "JE"
+    | "CAN";
+
+// Keep in sync with the names in the backend
+export const verdictNames: Record<Verdict, string> = {
+    AC: "Passed",
+    WA: "Logic error",
+    TLE: "Time limit exceeded",
+    MLE: "Memory limit exceeded",
+    RE: "Runtime error",
+    CE: "Compile error",
+    CTLE: "Compilation timed out",
+    OLE: "Output limit exceeded",
+    JE: "Judge error",
+    CAN: "Cancelled"
+};
+
+export type ResourceUsage = {
+    cpuTimeMs: number;
+    wallTimeMs: number;
+    memoryKb: number;
+};
+
+export type CaseReport = {
+    verdict: Verdict;
+    message: string | null;
+    exitCode: number | null;
+    signal: number | null;
+    usage: ResourceUsage | null;
+};
+
 export type CaseStatus =
     | {
           status: "running";
@@ -22,20 +70,44 @@ export type CaseStatus =
       }
     | {
           status: "passed";
-          content: string | null;
+          content: CaseReport;
       }
     | {
           status: "failed";
-          content: string;
+          content: CaseReport;
       }
     | {
           status: "notRun";
       };
 
+// Same as `CaseReport::describe` in the backend
+export const describeReport = (report: CaseReport) => {
+    let desc = verdictNames[report.verdict];
+    if (report.signal !== null) {
+        desc += ` (signal ${report.signal})`;
+    } else if (report.exitCode !== null) {
+        desc += ` (exit code ${report.exitCode})`;
+    }
+    if (report.verdict === "WA" && report.message) {
+        desc += `: ${report.message}`;
+    }
+    return desc;
+};
+
+const describeUsage = (usage: ResourceUsage | null) =>
+    usage
+        ? `${usage.cpuTimeMs}ms CPU, ${usage.wallTimeMs}ms wall, ${Math.ceil(usage.memoryKb / 1024)}MB`
+        : null;
+
 export type JobState =
+    | {
+          type: "queued";
+          position: number;
+      }
     | {
           type: "judging";
           cases: CaseStatus[];
+          samples: boolean;
       }
     | {
           type: "testing";
@@ -54,6 +126,10 @@ export type WebSocketMessage =
           type: "runDenied";
           reason: string;
       }
+    | {
+          type: "cancelDenied";
+          reason: string;
+      }
     | {
           type: "invalid";
           error: string;
@@ -73,6 +149,8 @@ export default (
 
     const stateIsComplete = (state: JobState) => {
         switch (state.type) {
+            case "queued":
+                return false;
             case "judging":
                 return !state.cases.some((c) => c.status === "pending" || c.status === "running");
             case "testing":
@@ -103,17 +181,49 @@ export default (
                 const complete = stateIsComplete(state);
                 toggleButtons(!complete);
                 switch (state.type) {
+                    case "queued":
+                        runMessageWrapper.setAttribute("data-status", "loading");
+                        runMessage.innerText = `Queued (#${state.position})...`;
+                        break;
                     case "judging":
+                        if (state.samples) {
+                            // Sample runs aren't judged, so show them with the test output instead of the progress
+                            const failed = state.cases.some((c) => c.status === "failed");
+                            debugCaseIndicator.setAttribute(
+                                "data-status",
+                                complete ? (failed ? "error" : "success") : "loading"
+                            );
+                            testOutput.value = state.cases
+                                .map((c, i) => {
+                                    if (c.status === "passed" || c.status === "failed") {
+                                        const usage = describeUsage(c.content.usage);
+                                        return `Sample ${i + 1}: ${describeReport(c.content)}${usage ? ` (${usage})` : ""}`;
+                                    }
+                                    return `Sample ${i + 1}: ${c.status}`;
+                                })
+                                .join("\n");
+                            break;
+                        }
                         for (const [i, c] of state.cases.entries()) {
-                            document
-                                .querySelector(`[data-case-number='${i}']`)!
-                                .setAttribute("data-status", typeToStatus[c.status]);
+                            const indicator = document.querySelector(`[data-case-number='${i}']`)!;
+                            indicator.setAttribute("data-status", typeToStatus[c.status]);
+                            if (c.status === "passed" || c.status === "failed") {
+                                const usage = describeUsage(c.content.usage);
+                                indicator.setAttribute(
+                                    "title",
+                                    `${describeReport(c.content)}${usage ? `\n${usage}` : ""}`
+                                );
+                            }
                         }
                         if (complete) {
                             const firstWithErr = state.cases.find((c) => c.status === "failed");
                             if (firstWithErr && firstWithErr.status === "failed") {
                                 runMessageWrapper.setAttribute("data-status", "error");
-                                runMessage.innerText = firstWithErr.content;
+                                runMessage.innerText = describeReport(firstWithErr.content);
+                                if (firstWithErr.content.verdict === "CE" && firstWithErr.content.message) {
+                                    // Show what the compiler said so the code can be fixed
+                                    testOutput.value = firstWithErr.content.message;
+                                }
                             } else {
                                 runMessageWrapper.setAttribute("data-status", "success");
                                 runMessage.innerText = "Passed!";
@@ -130,10 +240,14 @@ export default (
                         );
                         switch (state.status.status) {
                             case "passed":
-                                testOutput.value = state.status.content ?? "";
+                                testOutput.value = state.status.content.message ?? "";
                                 break;
                             case "failed":
-                                testOutput.value = state.status.content ?? "";
+                                const report = state.status.content;
+                                testOutput.value =
+                                    report.message && report.verdict !== "WA"
+                                        ? `${describeReport(report)}: ${report.message}`
+                                    : describeReport(report);
                                 break;
                         }
                 }
@@ -145,6 +259,9 @@ export default (
                 runMessageWrapper.setAttribute("data-status", "error");
                 runMessage.innerText = message.reason;
                 break;
+            case "cancelDenied":
+                console.warn("Couldn't cancel job", message.reason);
+                break;
             case "runStarted":
                 toggleButtons(true);
                 break;
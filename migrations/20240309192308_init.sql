@@ -9,6 +9,7 @@ CREATE TABLE IF NOT EXISTS user (
     display_name VARCHAR(32),
     default_language TEXT NOT NULL,
     color_scheme TEXT NOT NULL,
+    role TEXT NOT NULL DEFAULT 'Contestant',
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
 );
 
@@ -29,6 +30,10 @@ CREATE TABLE IF NOT EXISTS contest (
     end_time TIMESTAMP NOT NULL,
     registration_deadline TIMESTAMP NOT NULL,
     max_participants INTEGER,
+    penalty INTEGER NOT NULL DEFAULT 20,
+    scoring TEXT NOT NULL DEFAULT 'Icpc',
+    freeze_time TIMESTAMP,
+    revealed_until TIMESTAMP,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 
@@ -38,14 +43,42 @@ CREATE TABLE IF NOT EXISTS participant (
     contest_id INTEGER NOT NULL,
     registered_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE,
-    FOREIGN KEY (contest_id) REFERENCES contest(id) ON DELETE CASCADE
+    FOREIGN KEY (contest_id) REFERENCES contest(id) ON DELETE CASCADE,
+    UNIQUE (user_id, contest_id)
 );
 
 CREATE TABLE IF NOT EXISTS problem (
     id INTEGER PRIMARY KEY NOT NULL,
     name VARCHAR(100) NOT NULL,
     description TEXT NOT NULL,
-    cpu_time INTEGER NOT NULL
+    cpu_time INTEGER NOT NULL,
+    memory_limit INTEGER NOT NULL,
+    checker_language TEXT,
+    checker_program TEXT,
+    interactive BOOLEAN NOT NULL DEFAULT FALSE,
+    run_all_cases BOOLEAN NOT NULL DEFAULT FALSE,
+    owner_id INTEGER,
+    FOREIGN KEY (owner_id) REFERENCES user(id) ON DELETE SET NULL
+);
+
+CREATE TABLE problem_author (
+    id INTEGER PRIMARY KEY NOT NULL,
+    problem_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    FOREIGN KEY (problem_id) REFERENCES problem(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE,
+    UNIQUE (problem_id, user_id)
+);
+
+CREATE TABLE contest_problem (
+    id INTEGER PRIMARY KEY NOT NULL,
+    contest_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    ord INTEGER NOT NULL,
+    FOREIGN KEY (contest_id) REFERENCES contest(id) ON DELETE CASCADE,
+    FOREIGN KEY (problem_id) REFERENCES problem(id) ON DELETE CASCADE,
+    UNIQUE (contest_id, problem_id),
+    UNIQUE (contest_id, ord)
 );
 
 CREATE TABLE test_case (
@@ -54,8 +87,12 @@ CREATE TABLE test_case (
     ord INTEGER NOT NULL,
     stdin TEXT NOT NULL,
     expected_pattern TEXT NOT NULL,
-    use_regex BOOLEAN NOT NULL,
+    compare_mode TEXT NOT NULL,
     case_insensitive BOOLEAN NOT NULL,
+    epsilon REAL NOT NULL,
+    test_group INTEGER NOT NULL DEFAULT 0,
+    points INTEGER NOT NULL DEFAULT 1,
+    is_sample BOOLEAN NOT NULL DEFAULT FALSE,
     FOREIGN KEY (problem_id) REFERENCES problem(id) ON DELETE CASCADE
     UNIQUE (problem_id, ord)
 );
@@ -67,6 +104,40 @@ CREATE TABLE judge_run (
     amount_run INTEGER NOT NULL,
     total_cases INTEGER NOT NULL,
     error TEXT,
+    verdict TEXT,
+    score INTEGER NOT NULL DEFAULT 0,
+    max_score INTEGER NOT NULL DEFAULT 0,
+    program TEXT NOT NULL DEFAULT '',
+    language TEXT NOT NULL DEFAULT '',
+    compile_output TEXT,
     ran_at TIMESTAMP NOT NULL
 );
 
+CREATE TABLE judge_run_case (
+    id INTEGER PRIMARY KEY NOT NULL,
+    run_id INTEGER NOT NULL,
+    case_index INTEGER NOT NULL,
+    status TEXT NOT NULL,
+    verdict TEXT,
+    message TEXT,
+    exit_code INTEGER,
+    signal INTEGER,
+    cpu_time_ms INTEGER,
+    wall_time_ms INTEGER,
+    memory_kb INTEGER,
+    FOREIGN KEY (run_id) REFERENCES judge_run(id) ON DELETE CASCADE,
+    UNIQUE(run_id, case_index)
+);
+
+CREATE TABLE queued_job (
+    id INTEGER PRIMARY KEY NOT NULL,
+    user_id INTEGER NOT NULL,
+    problem_id INTEGER NOT NULL,
+    language TEXT NOT NULL,
+    program TEXT NOT NULL,
+    rejudge_run_id INTEGER,
+    queued_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES user(id) ON DELETE CASCADE,
+    FOREIGN KEY (problem_id) REFERENCES problem(id) ON DELETE CASCADE,
+    FOREIGN KEY (rejudge_run_id) REFERENCES judge_run(id) ON DELETE CASCADE
+);
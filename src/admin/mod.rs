@@ -0,0 +1,9 @@
+use rocket::{fairing::AdHoc, routes};
+
+mod users;
+
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Admin Stage", |rocket| async {
+        rocket.mount("/admin", routes![users::users_get, users::set_role_post])
+    })
+}
@@ -0,0 +1,72 @@
+use log::error;
+use rocket::{form::Form, get, http::Status, post, response::Redirect, FromForm};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{
+        csrf::{CsrfToken, VerifyCsrfToken},
+        users::{Admin, Role},
+    },
+    context_with_base_authed,
+    db::DbConnection,
+};
+
+#[derive(Serialize)]
+struct UserRole {
+    id: i64,
+    display_name: String,
+    email: String,
+    role: Role,
+}
+
+#[get("/users")]
+pub async fn users_get(user: &Admin, mut db: DbConnection, _token: &CsrfToken) -> Template {
+    let users = sqlx::query_as!(
+        UserRole,
+        r#"SELECT id, COALESCE(display_name, default_display_name) AS "display_name!: String", email, role FROM user ORDER BY id"#
+    )
+    .fetch_all(&mut **db)
+    .await
+    .unwrap_or_else(|e| {
+        error!("Couldn't list users: {:?}", e);
+        vec![]
+    });
+    Template::render("admin/users", context_with_base_authed!(user, users))
+}
+
+#[derive(FromForm)]
+pub struct RoleForm {
+    role: Role,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum SetRoleResponse {
+    Redirect(Redirect),
+    Error(Status),
+}
+
+#[post("/users/<id>/role", data = "<form>")]
+pub async fn set_role_post(
+    id: i64,
+    user: &Admin,
+    form: Form<RoleForm>,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> SetRoleResponse {
+    // Admins can't demote themselves so there's always at least one admin
+    if id == user.id {
+        return SetRoleResponse::Error(Status::Conflict);
+    }
+    let res = sqlx::query!("UPDATE user SET role = ? WHERE id = ?", form.role, id)
+        .execute(&mut **db)
+        .await;
+    match res {
+        Ok(res) if res.rows_affected() == 0 => SetRoleResponse::Error(Status::NotFound),
+        Ok(_) => SetRoleResponse::Redirect(Redirect::to("/admin/users")),
+        Err(why) => {
+            error!("Couldn't set role for user {}: {:?}", id, why);
+            SetRoleResponse::Error(Status::InternalServerError)
+        }
+    }
+}
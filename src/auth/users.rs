@@ -58,6 +58,66 @@ impl Encode<'_, sqlx::Sqlite> for ColorScheme {
     }
 }
 
+/// What a user is allowed to do, each role can do everything a contestant can
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, FromFormField)]
+pub enum Role {
+    #[default]
+    Contestant,
+    /// Can create and edit problems
+    ProblemSetter,
+    /// Can see live results and run the scoreboard reveal
+    Judge,
+    /// Can do anything, including managing contests and other users' roles
+    Admin,
+}
+
+impl Role {
+    pub fn is_admin(&self) -> bool {
+        *self == Self::Admin
+    }
+
+    pub fn can_set_problems(&self) -> bool {
+        matches!(self, Self::ProblemSetter | Self::Admin)
+    }
+
+    pub fn can_judge(&self) -> bool {
+        matches!(self, Self::Judge | Self::Admin)
+    }
+}
+
+impl From<String> for Role {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "ProblemSetter" => Self::ProblemSetter,
+            "Judge" => Self::Judge,
+            "Admin" => Self::Admin,
+            _ => Self::Contestant,
+        }
+    }
+}
+
+impl From<Role> for String {
+    fn from(r: Role) -> Self {
+        format!("{:?}", r)
+    }
+}
+
+impl Type<sqlx::Sqlite> for Role {
+    fn type_info() -> <sqlx::Sqlite as sqlx::Database>::TypeInfo {
+        <String as Type<sqlx::Sqlite>>::type_info()
+    }
+}
+
+impl Encode<'_, sqlx::Sqlite> for Role {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <sqlx::Sqlite as sqlx::database::HasArguments<'_>>::ArgumentBuffer,
+    ) -> IsNull {
+        let val = format!("{:?}", self);
+        <std::string::String as Encode<'_, sqlx::Sqlite>>::encode_by_ref(&val, buf)
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct User {
     pub id: i64,
@@ -67,6 +127,7 @@ pub struct User {
     pub display_name: Option<String>,
     pub color_scheme: ColorScheme,
     pub default_language: String,
+    pub role: Role,
     #[serde(skip)] // Not implemented, I cry
     pub created_at: OffsetDateTime,
 }
@@ -91,6 +152,7 @@ impl User {
             color_scheme: ColorScheme::default(),
             default_language: default_language.to_string(),
             display_name: None,
+            role: Role::default(),
             created_at: OffsetDateTime::now_utc(),
         }
     }
@@ -123,14 +185,16 @@ impl User {
         Ok(())
     }
 
+    /// Save a new user, the first user to sign up is made an admin so someone can hand out roles
     pub async fn write_to_db(self, db: &mut DbPoolConnection) -> Result<User, String> {
         let new = sqlx::query_as!(
             User,
-            "INSERT INTO user (email, default_display_name, color_scheme, default_language) VALUES (?, ?, ?, ?) RETURNING *",
+            "INSERT INTO user (email, default_display_name, color_scheme, default_language, role) VALUES (?, ?, ?, ?, (SELECT CASE WHEN COUNT(*) = 0 THEN 'Admin' ELSE ? END FROM user)) RETURNING *",
             self.email,
             self.default_display_name,
             self.color_scheme,
-            self.default_language
+            self.default_language,
+            self.role
         )
         .fetch_one(&mut **db)
         .await
@@ -176,3 +240,54 @@ impl<'r> FromRequest<'r> for &'r User {
         user_result.as_ref().or_forward(Status::Unauthorized)
     }
 }
+
+/// Defines a request guard for users with a role that passes `$check`, forwards with
+/// [Status::Forbidden] if the user is logged in but doesn't have the role
+macro_rules! role_guard {
+    ($(#[$meta:meta])* $name:ident, $check:ident) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Serialize)]
+        #[serde(transparent)]
+        pub struct $name(pub User);
+
+        impl std::ops::Deref for $name {
+            type Target = User;
+
+            fn deref(&self) -> &User {
+                &self.0
+            }
+        }
+
+        #[rocket::async_trait]
+        impl<'r> FromRequest<'r> for &'r $name {
+            type Error = ();
+
+            async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+                let user = rocket::outcome::try_outcome!(req.guard::<&User>().await);
+                req.local_cache(|| {
+                    Some(user)
+                        .filter(|u| u.role.$check())
+                        .map(|u| $name(u.clone()))
+                })
+                .as_ref()
+                .or_forward(Status::Forbidden)
+            }
+        }
+    };
+}
+
+role_guard!(
+    /// A logged in user who is an admin
+    Admin,
+    is_admin
+);
+role_guard!(
+    /// A logged in user who can create and edit problems
+    ProblemSetter,
+    can_set_problems
+);
+role_guard!(
+    /// A logged in user who can see live results
+    Judge,
+    can_judge
+);
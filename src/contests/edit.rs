@@ -0,0 +1,91 @@
+use log::error;
+use rocket::{
+    form::{Contextual, Form},
+    get,
+    http::Status,
+    post,
+};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{
+        csrf::{CsrfToken, VerifyCsrfToken},
+        users::Admin,
+    },
+    context_with_base_authed,
+    db::DbConnection,
+    template::{FormStatus, FormTemplateObject},
+};
+
+use super::{Contest, ContestForm, ContestFormTemplate};
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ContestEditResponse {
+    Form(Template),
+    NotFound(Status),
+}
+
+#[get("/<id>/edit")]
+pub async fn edit_contest_get(
+    user: &Admin,
+    mut db: DbConnection,
+    id: i64,
+    _token: &CsrfToken,
+) -> ContestEditResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        let form_template = ContestFormTemplate {
+            contest: Some(&contest),
+        };
+        let form = FormTemplateObject::get(form_template);
+        ContestEditResponse::Form(Template::render(
+            "contests/edit",
+            context_with_base_authed!(user, form, contest_name: contest.name, contest_id: contest.id),
+        ))
+    } else {
+        ContestEditResponse::NotFound(Status::NotFound)
+    }
+}
+
+#[post("/<id>/edit", data = "<form>")]
+pub async fn edit_contest_post(
+    id: i64,
+    user: &Admin,
+    form: Form<Contextual<'_, ContestForm<'_>>>,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> ContestEditResponse {
+    if let Some(mut contest) = Contest::get(&mut db, id).await {
+        let original_name = contest.name.clone();
+
+        if let Some(ref value) = form.value {
+            contest.update_from_form(value);
+            let status = if let Err(why) = contest.update(&mut db).await {
+                error!("Failed to update contest: {:?}", why);
+                FormStatus::Error
+            } else {
+                FormStatus::Success
+            };
+            let form_template = ContestFormTemplate {
+                contest: Some(&contest),
+            };
+            let mut form_ctx = FormTemplateObject::get(form_template);
+            form_ctx.status = status;
+            ContestEditResponse::Form(Template::render(
+                "contests/edit",
+                context_with_base_authed!(user, form: form_ctx, contest_name: original_name, contest_id: contest.id),
+            ))
+        } else {
+            let form_template = ContestFormTemplate {
+                contest: Some(&contest),
+            };
+            let form_ctx = FormTemplateObject::from_rocket_context(form_template, &form.context);
+            ContestEditResponse::Form(Template::render(
+                "contests/edit",
+                context_with_base_authed!(user, form: form_ctx, contest_name: original_name, contest_id: contest.id),
+            ))
+        }
+    } else {
+        ContestEditResponse::NotFound(Status::NotFound)
+    }
+}
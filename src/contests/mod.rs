@@ -0,0 +1,359 @@
+#![allow(clippy::blocks_in_conditions)] // Needed for the derive of FromForm, rocket is weird
+
+use std::collections::HashMap;
+
+use rocket::{
+    fairing::AdHoc,
+    routes,
+    time::{OffsetDateTime, PrimitiveDateTime},
+    FromForm, FromFormField,
+};
+use sqlx::{encode::IsNull, Decode, Encode, Type};
+use time::{format_description::FormatItem, macros::format_description};
+
+use crate::{db::DbPoolConnection, template::TemplatedForm};
+
+mod edit;
+mod new;
+mod participants;
+mod problems;
+mod scoreboard;
+mod view;
+
+pub use participants::Participant;
+pub use problems::ContestProblem;
+
+/// Format used by `datetime-local` inputs
+const FORM_DATETIME_FORMAT: &[FormatItem] =
+    format_description!("[year]-[month]-[day]T[hour]:[minute]");
+
+/// How participants are ranked on the scoreboard
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, FromFormField)]
+pub enum ScoringStyle {
+    /// Ranked by problems solved, then by penalty time
+    Icpc,
+    /// Ranked by the sum of each participant's best score on each problem
+    Ioi,
+}
+
+impl From<String> for ScoringStyle {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "Ioi" => Self::Ioi,
+            _ => Self::Icpc,
+        }
+    }
+}
+
+impl From<ScoringStyle> for String {
+    fn from(s: ScoringStyle) -> Self {
+        format!("{:?}", s)
+    }
+}
+
+impl Type<sqlx::Sqlite> for ScoringStyle {
+    fn type_info() -> <sqlx::Sqlite as sqlx::Database>::TypeInfo {
+        <String as Type<sqlx::Sqlite>>::type_info()
+    }
+}
+
+impl Encode<'_, sqlx::Sqlite> for ScoringStyle {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <sqlx::Sqlite as sqlx::database::HasArguments<'_>>::ArgumentBuffer,
+    ) -> IsNull {
+        let val = format!("{:?}", self);
+        <std::string::String as Encode<'_, sqlx::Sqlite>>::encode_by_ref(&val, buf)
+    }
+}
+
+impl<'r> Decode<'r, sqlx::Sqlite> for ScoringStyle {
+    fn decode(
+        value: <sqlx::Sqlite as sqlx::database::HasValueRef<'r>>::ValueRef,
+    ) -> Result<Self, sqlx::error::BoxDynError> {
+        <String as Decode<'r, sqlx::Sqlite>>::decode(value).map(Self::from)
+    }
+}
+
+#[derive(Serialize)]
+pub struct Contest {
+    pub id: i64,
+    pub name: String,
+    pub description: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub start_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub end_time: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub registration_deadline: OffsetDateTime,
+    pub max_participants: Option<i64>,
+    /// Minutes added to a participant's penalty time for each rejected attempt before they solve a problem
+    pub penalty: i64,
+    pub scoring: ScoringStyle,
+    /// When the public scoreboard stops showing new results
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub freeze_time: Option<OffsetDateTime>,
+    /// How far through the frozen period the public scoreboard has been revealed
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub revealed_until: Option<OffsetDateTime>,
+    #[serde(skip)]
+    pub created_at: Option<OffsetDateTime>,
+}
+
+impl Contest {
+    pub async fn get(db: &mut DbPoolConnection, id: i64) -> Option<Self> {
+        sqlx::query_as!(Contest, "SELECT * FROM contest WHERE id = ?", id)
+            .fetch_one(&mut **db)
+            .await
+            .ok()
+    }
+
+    pub async fn list(db: &mut DbPoolConnection) -> Vec<Self> {
+        sqlx::query_as!(Contest, "SELECT * FROM contest ORDER BY start_time DESC")
+            .fetch_all(&mut **db)
+            .await
+            .unwrap()
+    }
+
+    pub async fn write_to_db(&self, db: &mut DbPoolConnection) -> Result<Contest, sqlx::Error> {
+        sqlx::query_as!(
+            Contest,
+            "INSERT INTO contest (name, description, start_time, end_time, registration_deadline, max_participants, penalty, scoring, freeze_time) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
+            self.name,
+            self.description,
+            self.start_time,
+            self.end_time,
+            self.registration_deadline,
+            self.max_participants,
+            self.penalty,
+            self.scoring,
+            self.freeze_time
+        )
+        .fetch_one(&mut **db)
+        .await
+    }
+
+    pub async fn update(&self, db: &mut DbPoolConnection) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "UPDATE contest SET name = ?, description = ?, start_time = ?, end_time = ?, registration_deadline = ?, max_participants = ?, penalty = ?, scoring = ?, freeze_time = ?, revealed_until = ? WHERE id = ?",
+            self.name,
+            self.description,
+            self.start_time,
+            self.end_time,
+            self.registration_deadline,
+            self.max_participants,
+            self.penalty,
+            self.scoring,
+            self.freeze_time,
+            self.revealed_until,
+            self.id
+        )
+        .execute(&mut **db)
+        .await
+        .map(|_| ())
+    }
+
+    pub fn started(&self) -> bool {
+        OffsetDateTime::now_utc() >= self.start_time
+    }
+
+    pub fn registration_open(&self) -> bool {
+        OffsetDateTime::now_utc() <= self.registration_deadline
+    }
+
+    pub fn ended(&self) -> bool {
+        OffsetDateTime::now_utc() > self.end_time
+    }
+
+    /// Runs made after this time are hidden from the public scoreboard, `None` if nothing is hidden.
+    /// This is the freeze time until the contest has ended and organisers start revealing results.
+    pub fn public_cutoff(&self) -> Option<OffsetDateTime> {
+        let freeze_time = self.freeze_time?;
+        if OffsetDateTime::now_utc() < freeze_time {
+            return None;
+        }
+        match self.revealed_until {
+            Some(revealed_until) if revealed_until >= self.end_time => None,
+            Some(revealed_until) => Some(revealed_until.max(freeze_time)),
+            None => Some(freeze_time),
+        }
+    }
+
+    pub fn temp(form: &ContestForm) -> Self {
+        Self {
+            id: 0,
+            name: form.name.to_string(),
+            description: Some(form.description.to_string()).filter(|d| !d.is_empty()),
+            start_time: form.start_time.assume_utc(),
+            end_time: form.end_time.assume_utc(),
+            registration_deadline: form.registration_deadline.assume_utc(),
+            max_participants: form.max_participants,
+            penalty: form.penalty,
+            scoring: form.scoring,
+            freeze_time: form.freeze_time.map(|t| t.assume_utc()),
+            revealed_until: None,
+            created_at: None,
+        }
+    }
+
+    /// Copy the fields set by the form onto this contest
+    pub fn update_from_form(&mut self, form: &ContestForm) {
+        *self = Self {
+            id: self.id,
+            revealed_until: self.revealed_until,
+            created_at: self.created_at,
+            ..Self::temp(form)
+        };
+    }
+}
+
+fn check_after<'v>(
+    time: &PrimitiveDateTime,
+    other: &PrimitiveDateTime,
+    msg: &'static str,
+) -> rocket::form::Result<'v, ()> {
+    if time > other {
+        Ok(())
+    } else {
+        Err(rocket::form::Error::validation(msg).into())
+    }
+}
+
+fn check_not_after<'v>(
+    time: &PrimitiveDateTime,
+    other: &PrimitiveDateTime,
+    msg: &'static str,
+) -> rocket::form::Result<'v, ()> {
+    if time <= other {
+        Ok(())
+    } else {
+        Err(rocket::form::Error::validation(msg).into())
+    }
+}
+
+fn check_freeze_time<'v>(
+    freeze_time: &Option<PrimitiveDateTime>,
+    start_time: &PrimitiveDateTime,
+    end_time: &PrimitiveDateTime,
+) -> rocket::form::Result<'v, ()> {
+    match freeze_time {
+        Some(time) if time < start_time || time > end_time => {
+            Err(rocket::form::Error::validation("Must be between the start and end time").into())
+        }
+        _ => Ok(()),
+    }
+}
+
+fn check_max_participants<'v>(max: &Option<i64>) -> rocket::form::Result<'v, ()> {
+    match max {
+        Some(max) if *max < 1 => {
+            Err(rocket::form::Error::validation("Must allow at least one participant").into())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[derive(FromForm)]
+pub struct ContestForm<'r> {
+    #[field(validate = len(1..=100))]
+    name: &'r str,
+    description: &'r str,
+    start_time: PrimitiveDateTime,
+    #[field(validate = check_after(&self.start_time, "Must be after the start time"))]
+    end_time: PrimitiveDateTime,
+    #[field(validate = check_not_after(&self.end_time, "Must be before the end time"))]
+    registration_deadline: PrimitiveDateTime,
+    #[field(validate = check_max_participants())]
+    max_participants: Option<i64>,
+    #[field(validate = range(0..=1000))]
+    penalty: i64,
+    scoring: ScoringStyle,
+    #[field(validate = check_freeze_time(&self.start_time, &self.end_time))]
+    freeze_time: Option<PrimitiveDateTime>,
+}
+
+pub struct ContestFormTemplate<'r> {
+    contest: Option<&'r Contest>,
+}
+
+fn format_form_time(time: &OffsetDateTime) -> String {
+    time.format(FORM_DATETIME_FORMAT).unwrap_or_default()
+}
+
+impl<'r> TemplatedForm for ContestFormTemplate<'r> {
+    fn get_defaults(&mut self) -> HashMap<String, String> {
+        if let Some(contest) = self.contest {
+            HashMap::from_iter([
+                ("name".to_string(), contest.name.clone()),
+                (
+                    "description".to_string(),
+                    contest.description.clone().unwrap_or_default(),
+                ),
+                (
+                    "start_time".to_string(),
+                    format_form_time(&contest.start_time),
+                ),
+                ("end_time".to_string(), format_form_time(&contest.end_time)),
+                (
+                    "registration_deadline".to_string(),
+                    format_form_time(&contest.registration_deadline),
+                ),
+                (
+                    "max_participants".to_string(),
+                    contest
+                        .max_participants
+                        .map(|m| m.to_string())
+                        .unwrap_or_default(),
+                ),
+                ("penalty".to_string(), contest.penalty.to_string()),
+                ("scoring".to_string(), contest.scoring.into()),
+                (
+                    "freeze_time".to_string(),
+                    contest
+                        .freeze_time
+                        .as_ref()
+                        .map(format_form_time)
+                        .unwrap_or_default(),
+                ),
+            ])
+        } else {
+            HashMap::from_iter([
+                ("name".to_string(), "".to_string()),
+                ("description".to_string(), "".to_string()),
+                ("start_time".to_string(), "".to_string()),
+                ("end_time".to_string(), "".to_string()),
+                ("registration_deadline".to_string(), "".to_string()),
+                ("max_participants".to_string(), "".to_string()),
+                ("penalty".to_string(), "20".to_string()),
+                ("scoring".to_string(), "Icpc".to_string()),
+                ("freeze_time".to_string(), "".to_string()),
+            ])
+        }
+    }
+}
+
+pub fn stage() -> AdHoc {
+    AdHoc::on_ignite("Contest Stage", |rocket| async {
+        rocket.mount(
+            "/contests",
+            routes![
+                view::list_contests_get,
+                view::view_contest_get,
+                new::new_contest_get,
+                new::new_contest_post,
+                edit::edit_contest_get,
+                edit::edit_contest_post,
+                participants::register_post,
+                participants::unregister_post,
+                participants::participants_get,
+                problems::problem_set_get,
+                problems::problem_set_post,
+                scoreboard::scoreboard_get,
+                scoreboard::scoreboard_events,
+                scoreboard::reveal_post,
+                scoreboard::unfreeze_post,
+                scoreboard::rejudge_post,
+            ],
+        )
+    })
+}
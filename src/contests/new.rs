@@ -0,0 +1,62 @@
+use log::error;
+use rocket::{
+    form::{Contextual, Form},
+    get, post,
+    response::Redirect,
+};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{
+        csrf::{CsrfToken, VerifyCsrfToken},
+        users::Admin,
+    },
+    context_with_base_authed,
+    db::DbConnection,
+    template::FormTemplateObject,
+};
+
+use super::{Contest, ContestForm, ContestFormTemplate};
+
+#[get("/new", rank = 5)]
+pub fn new_contest_get(user: &Admin, _token: &CsrfToken) -> Template {
+    let form_template = ContestFormTemplate { contest: None };
+    let form = FormTemplateObject::get(form_template);
+    Template::render("contests/new", context_with_base_authed!(user, form))
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ContestNewResponse {
+    Redirect(Redirect),
+    Error(Template),
+}
+
+#[post("/new", data = "<form>", rank = 5)]
+pub async fn new_contest_post(
+    user: &Admin,
+    form: Form<Contextual<'_, ContestForm<'_>>>,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> ContestNewResponse {
+    if let Some(ref value) = form.value {
+        let contest = Contest::temp(value);
+        match contest.write_to_db(&mut db).await {
+            Ok(contest) => {
+                ContestNewResponse::Redirect(Redirect::to(format!("/contests/{}", contest.id)))
+            }
+            Err(why) => {
+                error!("Error saving contest: {:?}", why);
+                ContestNewResponse::Redirect(Redirect::to("/contests"))
+            }
+        }
+    } else {
+        let form_template = ContestFormTemplate { contest: None };
+        let form = FormTemplateObject::from_rocket_context(form_template, &form.context);
+
+        ContestNewResponse::Error(Template::render(
+            "contests/new",
+            context_with_base_authed!(user, form),
+        ))
+    }
+}
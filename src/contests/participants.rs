@@ -0,0 +1,214 @@
+use log::error;
+use rocket::{
+    get,
+    http::Status,
+    outcome::IntoOutcome,
+    post,
+    request::{self, FromRequest},
+    response::Redirect,
+    time::OffsetDateTime,
+    Request,
+};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{
+        csrf::VerifyCsrfToken,
+        users::{Judge, User},
+    },
+    context_with_base_authed,
+    db::{DbConnection, DbPoolConnection},
+};
+
+use super::Contest;
+
+#[derive(Serialize)]
+pub struct Participant {
+    pub id: i64,
+    pub user_id: i64,
+    pub contest_id: i64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub registered_at: Option<OffsetDateTime>,
+}
+
+/// A participant along with who they are, for showing to organisers
+#[derive(Serialize)]
+pub struct Registrant {
+    pub user_id: i64,
+    pub display_name: String,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub registered_at: Option<OffsetDateTime>,
+}
+
+impl Participant {
+    pub async fn get(db: &mut DbPoolConnection, contest_id: i64, user_id: i64) -> Option<Self> {
+        sqlx::query_as!(
+            Participant,
+            "SELECT * FROM participant WHERE contest_id = ? AND user_id = ?",
+            contest_id,
+            user_id
+        )
+        .fetch_optional(&mut **db)
+        .await
+        .ok()
+        .flatten()
+    }
+
+    pub async fn count(db: &mut DbPoolConnection, contest_id: i64) -> Result<i64, sqlx::Error> {
+        sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!: i64" FROM participant WHERE contest_id = ?"#,
+            contest_id
+        )
+        .fetch_one(&mut **db)
+        .await
+        .map(|r| r.count)
+    }
+
+    pub async fn list(
+        db: &mut DbPoolConnection,
+        contest_id: i64,
+    ) -> Result<Vec<Registrant>, sqlx::Error> {
+        sqlx::query_as!(
+            Registrant,
+            r#"SELECT user.id AS user_id, COALESCE(user.display_name, user.default_display_name) AS "display_name!: String", participant.registered_at
+            FROM participant JOIN user ON participant.user_id = user.id
+            WHERE participant.contest_id = ? ORDER BY participant.registered_at"#,
+            contest_id
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Register a user for a contest if they aren't already and the contest isn't full.
+    /// Returns whether the user was registered.
+    pub async fn register(
+        db: &mut DbPoolConnection,
+        contest: &Contest,
+        user_id: i64,
+    ) -> Result<bool, sqlx::Error> {
+        // Checking the count in the same statement means two users can't take the last spot at once
+        sqlx::query!(
+            "INSERT INTO participant (user_id, contest_id) SELECT ?, ?
+            WHERE NOT EXISTS (SELECT 1 FROM participant WHERE user_id = ? AND contest_id = ?)
+            AND (? IS NULL OR (SELECT COUNT(*) FROM participant WHERE contest_id = ?) < ?)",
+            user_id,
+            contest.id,
+            user_id,
+            contest.id,
+            contest.max_participants,
+            contest.id,
+            contest.max_participants
+        )
+        .execute(&mut **db)
+        .await
+        .map(|r| r.rows_affected() == 1)
+    }
+
+    pub async fn delete(&self, db: &mut DbPoolConnection) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM participant WHERE id = ?", self.id)
+            .execute(&mut **db)
+            .await
+            .map(|_| ())
+    }
+}
+
+/// Requires the logged in user to be registered for the contest in the route.
+/// Only use this on routes mounted under `/contests/<id>`.
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for &'r Participant {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let participant_result = req
+            .local_cache_async(async {
+                let user = req.guard::<&User>().await.succeeded()?;
+                let contest_id = req.routed_segment(0)?.parse::<i64>().ok()?;
+                let mut db = req.guard::<DbConnection>().await.succeeded()?;
+                Participant::get(&mut db, contest_id, user.id).await
+            })
+            .await;
+
+        participant_result.as_ref().or_forward(Status::Forbidden)
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum RegistrationResponse {
+    Redirect(Redirect),
+    Error(Status),
+}
+
+#[post("/<id>/register")]
+pub async fn register_post(
+    id: i64,
+    user: &User,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> RegistrationResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        if !contest.registration_open() {
+            return RegistrationResponse::Error(Status::Forbidden);
+        }
+        match Participant::register(&mut db, &contest, user.id).await {
+            Ok(true) => RegistrationResponse::Redirect(Redirect::to(format!("/contests/{}", id))),
+            // Either already registered or the contest is full
+            Ok(false) => RegistrationResponse::Error(Status::Conflict),
+            Err(why) => {
+                error!(
+                    "Couldn't register user {} for contest {}: {:?}",
+                    user.id, id, why
+                );
+                RegistrationResponse::Error(Status::InternalServerError)
+            }
+        }
+    } else {
+        RegistrationResponse::Error(Status::NotFound)
+    }
+}
+
+#[post("/<id>/unregister")]
+pub async fn unregister_post(
+    id: i64,
+    participant: &Participant,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> RegistrationResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        if !contest.registration_open() {
+            return RegistrationResponse::Error(Status::Forbidden);
+        }
+        if let Err(why) = participant.delete(&mut db).await {
+            error!(
+                "Couldn't unregister user {} from contest {}: {:?}",
+                participant.user_id, id, why
+            );
+            return RegistrationResponse::Error(Status::InternalServerError);
+        }
+        RegistrationResponse::Redirect(Redirect::to(format!("/contests/{}", id)))
+    } else {
+        RegistrationResponse::Error(Status::NotFound)
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ParticipantsResponse {
+    Ok(Template),
+    NotFound(Status),
+}
+
+#[get("/<id>/participants")]
+pub async fn participants_get(id: i64, user: &Judge, mut db: DbConnection) -> ParticipantsResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        let participants = Participant::list(&mut db, contest.id)
+            .await
+            .unwrap_or_default();
+        ParticipantsResponse::Ok(Template::render(
+            "contests/participants",
+            context_with_base_authed!(user, contest, participants),
+        ))
+    } else {
+        ParticipantsResponse::NotFound(Status::NotFound)
+    }
+}
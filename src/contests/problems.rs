@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use log::error;
+use rocket::{
+    form::{Contextual, Form},
+    get,
+    http::Status,
+    post,
+    time::OffsetDateTime,
+    FromForm,
+};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{
+        csrf::{CsrfToken, VerifyCsrfToken},
+        users::{Admin, User},
+    },
+    context_with_base_authed,
+    db::{DbConnection, DbPoolConnection},
+    problems::Problem,
+    template::{FormStatus, FormTemplateObject, TemplatedForm},
+};
+
+use super::Contest;
+
+/// Get the label for the problem at the given position in a contest, A-Z then AA, AB, etc.
+pub fn problem_letter(ord: i64) -> String {
+    let mut n = ord;
+    let mut letter = String::new();
+    loop {
+        letter.insert(0, (b'A' + (n % 26) as u8) as char);
+        n = n / 26 - 1;
+        if n < 0 {
+            break letter;
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct ContestProblem {
+    pub problem_id: i64,
+    pub name: String,
+    pub ord: i64,
+    pub letter: String,
+}
+
+impl ContestProblem {
+    pub async fn list(
+        db: &mut DbPoolConnection,
+        contest_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query!(
+            "SELECT contest_problem.problem_id, problem.name, contest_problem.ord FROM contest_problem
+            JOIN problem ON problem.id = contest_problem.problem_id
+            WHERE contest_problem.contest_id = ? ORDER BY contest_problem.ord",
+            contest_id
+        )
+        .fetch_all(&mut **db)
+        .await
+        .map(|rows| {
+            rows.into_iter()
+                .map(|r| Self {
+                    problem_id: r.problem_id,
+                    name: r.name,
+                    ord: r.ord,
+                    letter: problem_letter(r.ord),
+                })
+                .collect()
+        })
+    }
+
+    /// Replace a contest's problem set, problems are given letters in the order they're passed
+    pub async fn save_for_contest(
+        db: &mut DbPoolConnection,
+        contest_id: i64,
+        problem_ids: &[i64],
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM contest_problem WHERE contest_id = ?",
+            contest_id
+        )
+        .execute(&mut **db)
+        .await?;
+        if problem_ids.is_empty() {
+            return Ok(());
+        }
+        let values_str = problem_ids
+            .iter()
+            .map(|_| "(?, ?, ?)")
+            .collect::<Vec<_>>()
+            .join(",");
+        let query_str = format!(
+            "INSERT INTO contest_problem (contest_id, problem_id, ord) VALUES {}",
+            values_str
+        );
+        let mut query = sqlx::query(&query_str);
+        for (i, problem_id) in problem_ids.iter().enumerate() {
+            query = query.bind(contest_id).bind(problem_id).bind(i as i64);
+        }
+        query.execute(&mut **db).await.map(|_| ())
+    }
+
+    /// Whether a problem is part of a contest that hasn't started yet, meaning `user` can't see it.
+    /// Judges and the problem's authors can always see it so they can prepare it.
+    pub async fn is_hidden(
+        db: &mut DbPoolConnection,
+        problem: &Problem,
+        user: Option<&User>,
+    ) -> bool {
+        if let Some(user) = user {
+            if user.role.can_judge() || problem.can_edit(db, user).await {
+                return false;
+            }
+        }
+        let problem_id = problem.id;
+        let now = OffsetDateTime::now_utc();
+        sqlx::query!(
+            "SELECT contest.id FROM contest_problem JOIN contest ON contest.id = contest_problem.contest_id
+            WHERE contest_problem.problem_id = ? AND contest.start_time > ?",
+            problem_id,
+            now
+        )
+        .fetch_optional(&mut **db)
+        .await
+        .map(|r| r.is_some())
+        .unwrap_or_else(|e| {
+            error!("Couldn't check if problem {} is hidden: {:?}", problem_id, e);
+            true
+        })
+    }
+}
+
+#[derive(FromForm)]
+pub struct ProblemSetForm {
+    /// Position of each problem in the contest, problems without one aren't in the contest
+    problems: HashMap<i64, Option<i64>>,
+}
+
+impl ProblemSetForm {
+    fn ordered_ids(&self, valid_ids: &[i64]) -> Vec<i64> {
+        let mut positions = self
+            .problems
+            .iter()
+            .filter(|(id, _)| valid_ids.contains(id))
+            .filter_map(|(id, pos)| pos.map(|pos| (pos, *id)))
+            .collect::<Vec<_>>();
+        positions.sort();
+        positions.into_iter().map(|(_, id)| id).collect()
+    }
+}
+
+pub struct ProblemSetFormTemplate<'r> {
+    problems: &'r [Problem],
+    contest_problems: &'r [ContestProblem],
+}
+
+impl<'r> TemplatedForm for ProblemSetFormTemplate<'r> {
+    fn get_defaults(&mut self) -> HashMap<String, String> {
+        self.problems
+            .iter()
+            .map(|p| {
+                let position = self
+                    .contest_problems
+                    .iter()
+                    .find(|cp| cp.problem_id == p.id)
+                    .map(|cp| (cp.ord + 1).to_string())
+                    .unwrap_or_default();
+                (format!("problems[{}]", p.id), position)
+            })
+            .collect()
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ProblemSetResponse {
+    Form(Template),
+    NotFound(Status),
+}
+
+#[get("/<id>/problems")]
+pub async fn problem_set_get(
+    id: i64,
+    user: &Admin,
+    mut db: DbConnection,
+    _token: &CsrfToken,
+) -> ProblemSetResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        let problems = Problem::list(&mut db).await;
+        let contest_problems = ContestProblem::list(&mut db, contest.id)
+            .await
+            .unwrap_or_default();
+        let form_template = ProblemSetFormTemplate {
+            problems: &problems,
+            contest_problems: &contest_problems,
+        };
+        let form = FormTemplateObject::get(form_template);
+        ProblemSetResponse::Form(Template::render(
+            "contests/problems",
+            context_with_base_authed!(user, form, contest, problems),
+        ))
+    } else {
+        ProblemSetResponse::NotFound(Status::NotFound)
+    }
+}
+
+#[post("/<id>/problems", data = "<form>")]
+pub async fn problem_set_post(
+    id: i64,
+    user: &Admin,
+    form: Form<Contextual<'_, ProblemSetForm>>,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> ProblemSetResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        let problems = Problem::list(&mut db).await;
+        let status = if let Some(ref value) = form.value {
+            let valid_ids = problems.iter().map(|p| p.id).collect::<Vec<_>>();
+            let problem_ids = value.ordered_ids(&valid_ids);
+            if let Err(why) =
+                ContestProblem::save_for_contest(&mut db, contest.id, &problem_ids).await
+            {
+                error!(
+                    "Couldn't save problems for contest {}: {:?}",
+                    contest.id, why
+                );
+                FormStatus::Error
+            } else {
+                FormStatus::Success
+            }
+        } else {
+            FormStatus::Error
+        };
+        let contest_problems = ContestProblem::list(&mut db, contest.id)
+            .await
+            .unwrap_or_default();
+        let form_template = ProblemSetFormTemplate {
+            problems: &problems,
+            contest_problems: &contest_problems,
+        };
+        let mut form = FormTemplateObject::get(form_template);
+        form.status = status;
+        ProblemSetResponse::Form(Template::render(
+            "contests/problems",
+            context_with_base_authed!(user, form, contest, problems),
+        ))
+    } else {
+        ProblemSetResponse::NotFound(Status::NotFound)
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::HashMap;
+
+use log::{error, info};
+use rocket::{
+    get,
+    http::Status,
+    post,
+    response::{
+        stream::{Event, EventStream},
+        Redirect,
+    },
+    time::OffsetDateTime,
+    Shutdown, State,
+};
+use rocket_dyn_templates::Template;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{
+    auth::{
+        csrf::{CsrfToken, VerifyCsrfToken},
+        users::{Judge, User},
+    },
+    context_with_base,
+    db::{DbConnection, DbPoolConnection},
+    problems::{rejudge_runs, JudgeRun},
+    run::{ManagerHandle, Verdict},
+};
+
+use super::{Contest, ContestProblem, Participant, ScoringStyle};
+
+/// How a participant did on one problem
+#[derive(Serialize, Default, Clone)]
+pub struct ProblemScore {
+    pub solved: bool,
+    /// Whether this participant was the first to solve the problem
+    pub first_solve: bool,
+    /// Rejected attempts, after the problem is solved further attempts aren't counted
+    pub attempts: i64,
+    /// Minutes since the start of the contest the problem was solved at
+    pub solve_minutes: Option<i64>,
+    /// Attempts made while the scoreboard is frozen that haven't been revealed yet
+    pub pending: i64,
+    /// Best score out of all attempts, used for IOI style contests
+    pub score: i64,
+}
+
+#[derive(Serialize)]
+pub struct ScoreboardRow {
+    pub rank: usize,
+    pub user_id: i64,
+    pub display_name: String,
+    pub solved: i64,
+    pub penalty: i64,
+    pub score: i64,
+    pub problems: Vec<ProblemScore>,
+}
+
+impl ScoreboardRow {
+    /// Higher is better
+    fn rank_key(&self, scoring: ScoringStyle) -> (i64, i64) {
+        match scoring {
+            ScoringStyle::Icpc => (self.solved, -self.penalty),
+            ScoringStyle::Ioi => (self.score, 0),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct Scoreboard {
+    pub problems: Vec<ContestProblem>,
+    pub rows: Vec<ScoreboardRow>,
+}
+
+impl Scoreboard {
+    /// Tally up the judge runs made during a contest. For ICPC style contests participants are ranked by problems solved,
+    /// then by penalty time, which is the minutes taken to solve each problem plus the contest's penalty
+    /// for each rejected attempt on the problems they solved. For IOI style contests participants are ranked
+    /// by the sum of their best score on each problem.
+    ///
+    /// Runs made after `cutoff` are counted as pending instead of being scored, this is used for the frozen public view.
+    pub async fn build(
+        db: &mut DbPoolConnection,
+        contest: &Contest,
+        cutoff: Option<OffsetDateTime>,
+    ) -> Result<Self, sqlx::Error> {
+        let problems = ContestProblem::list(db, contest.id).await?;
+        let participants = Participant::list(db, contest.id).await?;
+
+        let runs = sqlx::query!(
+            r#"SELECT judge_run.user_id, judge_run.problem_id, judge_run.verdict, judge_run.score, judge_run.ran_at AS "ran_at: OffsetDateTime" FROM judge_run
+            JOIN contest_problem ON contest_problem.problem_id = judge_run.problem_id AND contest_problem.contest_id = ?
+            JOIN participant ON participant.user_id = judge_run.user_id AND participant.contest_id = ?
+            WHERE judge_run.ran_at >= ? AND judge_run.ran_at <= ?
+            ORDER BY judge_run.ran_at, judge_run.id"#,
+            contest.id,
+            contest.id,
+            contest.start_time,
+            contest.end_time
+        )
+        .fetch_all(&mut **db)
+        .await?;
+
+        let mut scores = HashMap::<(i64, i64), ProblemScore>::new();
+        let mut first_solvers = HashMap::<i64, i64>::new();
+
+        for run in runs {
+            let score = scores.entry((run.user_id, run.problem_id)).or_default();
+            if score.solved {
+                continue;
+            }
+            if cutoff.is_some_and(|cutoff| run.ran_at > cutoff) {
+                score.pending += 1;
+                continue;
+            }
+            score.score = score.score.max(run.score);
+            match run.verdict.as_deref() {
+                None => {
+                    score.solved = true;
+                    score.solve_minutes = Some((run.ran_at - contest.start_time).whole_minutes());
+                    // Runs are in order, so the first accept we see is the first solve
+                    first_solvers.entry(run.problem_id).or_insert(run.user_id);
+                }
+                Some(code) if Verdict::from_code(code).is_some_and(|v| !v.is_penalised()) => {}
+                Some(_) => {
+                    score.attempts += 1;
+                }
+            }
+        }
+
+        let mut rows = participants
+            .into_iter()
+            .map(|participant| {
+                let problem_scores = problems
+                    .iter()
+                    .map(|problem| {
+                        let mut score = scores
+                            .get(&(participant.user_id, problem.problem_id))
+                            .cloned()
+                            .unwrap_or_default();
+                        score.first_solve =
+                            first_solvers.get(&problem.problem_id) == Some(&participant.user_id);
+                        score
+                    })
+                    .collect::<Vec<_>>();
+                let solved = problem_scores.iter().filter(|s| s.solved).count() as i64;
+                let score = problem_scores.iter().map(|s| s.score).sum();
+                let penalty = problem_scores
+                    .iter()
+                    .filter_map(|s| s.solve_minutes.map(|m| m + s.attempts * contest.penalty))
+                    .sum();
+                ScoreboardRow {
+                    rank: 0,
+                    user_id: participant.user_id,
+                    display_name: participant.display_name,
+                    solved,
+                    penalty,
+                    score,
+                    problems: problem_scores,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        rows.sort_by(|a, b| {
+            b.rank_key(contest.scoring)
+                .cmp(&a.rank_key(contest.scoring))
+                .then(a.display_name.cmp(&b.display_name))
+        });
+
+        // Participants with the same score share a rank
+        for i in 0..rows.len() {
+            rows[i].rank = if i > 0
+                && rows[i - 1].rank_key(contest.scoring) == rows[i].rank_key(contest.scoring)
+            {
+                rows[i - 1].rank
+            } else {
+                i + 1
+            };
+        }
+
+        Ok(Self { problems, rows })
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ScoreboardResponse {
+    View(Template),
+    NotFound(Status),
+}
+
+#[get("/<id>/scoreboard")]
+pub async fn scoreboard_get(
+    id: i64,
+    user: Option<&User>,
+    mut db: DbConnection,
+    _token: &CsrfToken,
+) -> ScoreboardResponse {
+    match Contest::get(&mut db, id).await {
+        Some(contest) if contest.started() => {
+            // Judges see live results and run the reveal
+            let organiser = user.is_some_and(|u| u.role.can_judge());
+            let public_cutoff = contest.public_cutoff();
+            let frozen = public_cutoff.is_some();
+            let cutoff = if organiser { None } else { public_cutoff };
+            let scoreboard = Scoreboard::build(&mut db, &contest, cutoff).await;
+            match scoreboard {
+                Ok(scoreboard) => {
+                    let can_reveal = organiser && frozen && contest.ended();
+                    ScoreboardResponse::View(Template::render(
+                        "contests/scoreboard",
+                        context_with_base!(
+                            user, contest, scoreboard, frozen, organiser, can_reveal
+                        ),
+                    ))
+                }
+                Err(why) => {
+                    error!("Couldn't build scoreboard for contest {}: {:?}", id, why);
+                    ScoreboardResponse::NotFound(Status::InternalServerError)
+                }
+            }
+        }
+        _ => ScoreboardResponse::NotFound(Status::NotFound),
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum RevealResponse {
+    Redirect(Redirect),
+    Error(Status),
+}
+
+/// Load a contest that can have its results revealed
+async fn revealable_contest(
+    db: &mut DbPoolConnection,
+    id: i64,
+) -> Result<(Contest, OffsetDateTime), Status> {
+    let contest = Contest::get(db, id).await.ok_or(Status::NotFound)?;
+    if !contest.ended() {
+        return Err(Status::Conflict);
+    }
+    let cutoff = contest.public_cutoff().ok_or(Status::Conflict)?;
+    Ok((contest, cutoff))
+}
+
+async fn save_revealed(
+    db: &mut DbPoolConnection,
+    manager: &ManagerHandle,
+    mut contest: Contest,
+    revealed_until: OffsetDateTime,
+    revealed_run: Option<(i64, i64)>,
+) -> RevealResponse {
+    contest.revealed_until = Some(revealed_until);
+    if let Err(why) = contest.update(db).await {
+        error!(
+            "Couldn't reveal scoreboard for contest {}: {:?}",
+            contest.id, why
+        );
+        return RevealResponse::Error(Status::InternalServerError);
+    }
+    if let Some(run) = revealed_run {
+        manager.lock().await.notify_run_completed(run);
+    }
+    RevealResponse::Redirect(Redirect::to(format!("/contests/{}/scoreboard", contest.id)))
+}
+
+/// Reveal the next pending run on the public scoreboard
+#[post("/<id>/scoreboard/reveal")]
+pub async fn reveal_post(
+    id: i64,
+    _user: &Judge,
+    _token: &VerifyCsrfToken,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+) -> RevealResponse {
+    let (contest, cutoff) = match revealable_contest(&mut db, id).await {
+        Ok(res) => res,
+        Err(status) => return RevealResponse::Error(status),
+    };
+
+    let next = sqlx::query!(
+        r#"SELECT judge_run.user_id, judge_run.problem_id, judge_run.ran_at AS "ran_at: OffsetDateTime" FROM judge_run
+        JOIN contest_problem ON contest_problem.problem_id = judge_run.problem_id AND contest_problem.contest_id = ?
+        JOIN participant ON participant.user_id = judge_run.user_id AND participant.contest_id = ?
+        WHERE judge_run.ran_at > ? AND judge_run.ran_at <= ?
+        ORDER BY judge_run.ran_at LIMIT 1"#,
+        contest.id,
+        contest.id,
+        cutoff,
+        contest.end_time
+    )
+    .fetch_optional(&mut **db)
+    .await;
+
+    match next {
+        Ok(Some(run)) => {
+            save_revealed(
+                &mut db,
+                manager,
+                contest,
+                run.ran_at,
+                Some((run.user_id, run.problem_id)),
+            )
+            .await
+        }
+        // Nothing left to reveal, so the scoreboard can be unfrozen entirely
+        Ok(None) => {
+            let end_time = contest.end_time;
+            save_revealed(&mut db, manager, contest, end_time, None).await
+        }
+        Err(why) => {
+            error!(
+                "Couldn't get next run to reveal for contest {}: {:?}",
+                id, why
+            );
+            RevealResponse::Error(Status::InternalServerError)
+        }
+    }
+}
+
+/// Reveal every pending run at once
+#[post("/<id>/scoreboard/unfreeze")]
+pub async fn unfreeze_post(
+    id: i64,
+    user: &Judge,
+    _token: &VerifyCsrfToken,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+) -> RevealResponse {
+    match revealable_contest(&mut db, id).await {
+        Ok((contest, _)) => {
+            let end_time = contest.end_time;
+            // Any of the contest's problems will do to get its scoreboards to refresh
+            let first_problem = ContestProblem::list(&mut db, contest.id)
+                .await
+                .ok()
+                .and_then(|problems| problems.first().map(|p| (user.id, p.problem_id)));
+            save_revealed(&mut db, manager, contest, end_time, first_problem).await
+        }
+        Err(status) => RevealResponse::Error(status),
+    }
+}
+
+/// Judge every run made during the contest again, the scoreboard updates as they finish
+#[post("/<id>/rejudge")]
+pub async fn rejudge_post(
+    id: i64,
+    _user: &Judge,
+    _token: &VerifyCsrfToken,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+) -> RevealResponse {
+    let contest = match Contest::get(&mut db, id).await {
+        Some(contest) => contest,
+        None => return RevealResponse::Error(Status::NotFound),
+    };
+    match JudgeRun::list_for_contest(&mut db, &contest).await {
+        Ok(runs) => {
+            let queued = rejudge_runs(&mut db, manager, runs).await;
+            info!("Queued {} runs of contest {} to be rejudged", queued, id);
+            RevealResponse::Redirect(Redirect::to(format!("/contests/{}/scoreboard", id)))
+        }
+        Err(why) => {
+            error!("Couldn't list runs for contest {}: {:?}", id, why);
+            RevealResponse::Error(Status::InternalServerError)
+        }
+    }
+}
+
+/// Tells the scoreboard page to refresh whenever a run for one of the contest's problems is judged
+#[get("/<id>/scoreboard/events")]
+pub async fn scoreboard_events(
+    id: i64,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+    mut shutdown: Shutdown,
+) -> Option<EventStream![]> {
+    let contest = Contest::get(&mut db, id).await?;
+    let problem_ids = ContestProblem::list(&mut db, contest.id)
+        .await
+        .ok()?
+        .into_iter()
+        .map(|p| p.problem_id)
+        .collect::<Vec<_>>();
+
+    let mut rx = manager.lock().await.subscribe_run_completed();
+
+    Some(EventStream! {
+        loop {
+            let update = tokio::select! {
+                msg = rx.recv() => match msg {
+                    Ok((_, problem_id)) => problem_ids.contains(&problem_id),
+                    // We missed some runs, so one of them could've been for this contest
+                    Err(RecvError::Lagged(_)) => true,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            if update {
+                yield Event::data("update");
+            }
+        }
+    })
+}
@@ -0,0 +1,70 @@
+use rocket::{get, http::Status};
+use rocket_dyn_templates::Template;
+
+use crate::{
+    auth::{csrf::CsrfToken, users::User},
+    context_with_base,
+    db::DbConnection,
+};
+
+use super::{Contest, ContestProblem, Participant};
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum ContestViewResponse {
+    View(Template),
+    NotFound(Status),
+}
+
+#[get("/")]
+pub async fn list_contests_get(user: Option<&User>, mut db: DbConnection) -> Template {
+    let contests = Contest::list(&mut db).await;
+    Template::render("contests", context_with_base!(user, contests))
+}
+
+#[get("/<id>")]
+pub async fn view_contest_get(
+    user: Option<&User>,
+    mut db: DbConnection,
+    id: i64,
+    _token: &CsrfToken,
+) -> ContestViewResponse {
+    if let Some(contest) = Contest::get(&mut db, id).await {
+        let registered = if let Some(user) = user {
+            Participant::get(&mut db, contest.id, user.id)
+                .await
+                .is_some()
+        } else {
+            false
+        };
+        let participant_count = Participant::count(&mut db, contest.id).await.unwrap_or(0);
+        let started = contest.started();
+        // Problems are kept secret until the contest starts
+        let problems = if started {
+            ContestProblem::list(&mut db, contest.id)
+                .await
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+        let deadline_passed = !contest.registration_open();
+        let full = contest
+            .max_participants
+            .is_some_and(|max| participant_count >= max);
+        ContestViewResponse::View(Template::render(
+            "contests/view",
+            context_with_base!(
+                user,
+                contest,
+                registered,
+                participant_count,
+                deadline_passed,
+                full,
+                started,
+                problems
+            ),
+        ))
+    } else {
+        ContestViewResponse::NotFound(Status::NotFound)
+    }
+}
@@ -4,7 +4,9 @@ use rocket_dyn_templates::Template;
 #[macro_use]
 extern crate rocket_dyn_templates;
 
+mod admin;
 mod auth;
+mod contests;
 mod db;
 mod problems;
 mod run;
@@ -37,4 +39,6 @@ fn rocket() -> _ {
         .attach(auth::stage())
         .attach(settings::stage())
         .attach(problems::stage())
+        .attach(contests::stage())
+        .attach(admin::stage())
 }
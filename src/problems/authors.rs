@@ -0,0 +1,172 @@
+use log::error;
+use rocket::{form::Form, http::Status, post, response::Redirect, FromForm};
+
+use crate::{
+    auth::{csrf::VerifyCsrfToken, users::User},
+    db::{DbConnection, DbPoolConnection},
+};
+
+use super::Problem;
+
+/// Someone who wrote a problem
+#[derive(Serialize)]
+pub struct Author {
+    pub user_id: i64,
+    pub display_name: String,
+    pub email: String,
+}
+
+impl Author {
+    pub async fn owner(db: &mut DbPoolConnection, problem: &Problem) -> Option<Self> {
+        let owner_id = problem.owner_id?;
+        sqlx::query_as!(
+            Author,
+            r#"SELECT id AS user_id, COALESCE(display_name, default_display_name) AS "display_name!: String", email FROM user WHERE id = ?"#,
+            owner_id
+        )
+        .fetch_optional(&mut **db)
+        .await
+        .ok()
+        .flatten()
+    }
+
+    pub async fn list_co_authors(
+        db: &mut DbPoolConnection,
+        problem_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            Author,
+            r#"SELECT user.id AS user_id, COALESCE(user.display_name, user.default_display_name) AS "display_name!: String", user.email
+            FROM problem_author JOIN user ON problem_author.user_id = user.id
+            WHERE problem_author.problem_id = ? ORDER BY problem_author.id"#,
+            problem_id
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Add a co-author by their email, returns whether a user with that email exists
+    pub async fn add_co_author(
+        db: &mut DbPoolConnection,
+        problem_id: i64,
+        email: &str,
+    ) -> Result<bool, sqlx::Error> {
+        sqlx::query!(
+            "INSERT OR IGNORE INTO problem_author (problem_id, user_id) SELECT ?, id FROM user WHERE email = ?",
+            problem_id,
+            email
+        )
+        .execute(&mut **db)
+        .await?;
+        sqlx::query!("SELECT id FROM user WHERE email = ?", email)
+            .fetch_optional(&mut **db)
+            .await
+            .map(|u| u.is_some())
+    }
+
+    pub async fn remove_co_author(
+        db: &mut DbPoolConnection,
+        problem_id: i64,
+        user_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "DELETE FROM problem_author WHERE problem_id = ? AND user_id = ?",
+            problem_id,
+            user_id
+        )
+        .execute(&mut **db)
+        .await
+        .map(|_| ())
+    }
+}
+
+impl Problem {
+    /// Admins and the owner can change who the co-authors are
+    pub fn can_manage_authors(&self, user: &User) -> bool {
+        user.role.is_admin() || self.owner_id == Some(user.id)
+    }
+
+    /// Admins, the owner, and co-authors can edit the statement and test cases
+    pub async fn can_edit(&self, db: &mut DbPoolConnection, user: &User) -> bool {
+        if self.can_manage_authors(user) {
+            return true;
+        }
+        sqlx::query!(
+            "SELECT id FROM problem_author WHERE problem_id = ? AND user_id = ?",
+            self.id,
+            user.id
+        )
+        .fetch_optional(&mut **db)
+        .await
+        .map(|a| a.is_some())
+        .unwrap_or(false)
+    }
+}
+
+#[derive(FromForm)]
+pub struct AddAuthorForm<'r> {
+    email: &'r str,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum AuthorsResponse {
+    Redirect(Redirect),
+    Error(Status),
+}
+
+async fn manageable_problem(
+    db: &mut DbPoolConnection,
+    user: &User,
+    id: i64,
+) -> Result<Problem, Status> {
+    let problem = Problem::get(db, id).await.ok_or(Status::NotFound)?;
+    if problem.can_manage_authors(user) {
+        Ok(problem)
+    } else {
+        Err(Status::Forbidden)
+    }
+}
+
+#[post("/<id>/authors", data = "<form>")]
+pub async fn add_author_post(
+    id: i64,
+    user: &User,
+    form: Form<AddAuthorForm<'_>>,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> AuthorsResponse {
+    let problem = match manageable_problem(&mut db, user, id).await {
+        Ok(problem) => problem,
+        Err(status) => return AuthorsResponse::Error(status),
+    };
+    match Author::add_co_author(&mut db, problem.id, form.email.trim()).await {
+        Ok(true) => {
+            AuthorsResponse::Redirect(Redirect::to(format!("/problems/{}/edit", problem.id)))
+        }
+        Ok(false) => AuthorsResponse::Error(Status::NotFound),
+        Err(why) => {
+            error!("Couldn't add co-author to problem {}: {:?}", id, why);
+            AuthorsResponse::Error(Status::InternalServerError)
+        }
+    }
+}
+
+#[post("/<id>/authors/<user_id>/remove")]
+pub async fn remove_author_post(
+    id: i64,
+    user_id: i64,
+    user: &User,
+    _token: &VerifyCsrfToken,
+    mut db: DbConnection,
+) -> AuthorsResponse {
+    let problem = match manageable_problem(&mut db, user, id).await {
+        Ok(problem) => problem,
+        Err(status) => return AuthorsResponse::Error(status),
+    };
+    if let Err(why) = Author::remove_co_author(&mut db, problem.id, user_id).await {
+        error!("Couldn't remove co-author from problem {}: {:?}", id, why);
+        return AuthorsResponse::Error(Status::InternalServerError);
+    }
+    AuthorsResponse::Redirect(Redirect::to(format!("/problems/{}/edit", problem.id)))
+}
@@ -1,8 +1,65 @@
-use rocket::FromForm;
-use sqlx::prelude::FromRow;
+use std::collections::HashMap;
+
+use rocket::{FromForm, FromFormField};
+use sqlx::{encode::IsNull, prelude::FromRow, Decode, Encode, Type};
 
 use crate::db::DbPoolConnection;
 
+/// How the output of a program is compared to a case's expected output
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, FromFormField)]
+pub enum CompareMode {
+    /// Whole output must match, ignoring leading and trailing whitespace
+    Exact,
+    /// Output must have the same whitespace-separated tokens
+    Tokens,
+    /// Same as `Tokens`, but numbers can differ by an absolute or relative epsilon
+    Float,
+    /// Output must match the expected pattern as a regex
+    Regex,
+}
+
+impl From<String> for CompareMode {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "Exact" => Self::Exact,
+            "Tokens" => Self::Tokens,
+            "Float" => Self::Float,
+            "Regex" => Self::Regex,
+            _ => Self::Exact,
+        }
+    }
+}
+
+impl From<CompareMode> for String {
+    fn from(m: CompareMode) -> Self {
+        format!("{:?}", m)
+    }
+}
+
+impl Type<sqlx::Sqlite> for CompareMode {
+    fn type_info() -> <sqlx::Sqlite as sqlx::Database>::TypeInfo {
+        <String as Type<sqlx::Sqlite>>::type_info()
+    }
+}
+
+impl Encode<'_, sqlx::Sqlite> for CompareMode {
+    fn encode_by_ref(
+        &self,
+        buf: &mut <sqlx::Sqlite as sqlx::database::HasArguments<'_>>::ArgumentBuffer,
+    ) -> IsNull {
+        let val = format!("{:?}", self);
+        <std::string::String as Encode<'_, sqlx::Sqlite>>::encode_by_ref(&val, buf)
+    }
+}
+
+impl<'r> Decode<'r, sqlx::Sqlite> for CompareMode {
+    fn decode(
+        value: <sqlx::Sqlite as sqlx::database::HasValueRef<'r>>::ValueRef,
+    ) -> Result<Self, sqlx::error::BoxDynError> {
+        <String as Decode<'r, sqlx::Sqlite>>::decode(value).map(Self::from)
+    }
+}
+
 #[derive(Serialize, FromRow, Clone, Debug)]
 pub struct TestCase {
     id: i64,
@@ -10,8 +67,15 @@ pub struct TestCase {
     ord: i64,
     pub stdin: String,
     pub expected_pattern: String,
-    pub use_regex: bool,
+    pub compare_mode: CompareMode,
     pub case_insensitive: bool,
+    /// Max difference allowed between numbers when using [CompareMode::Float]
+    pub epsilon: f64,
+    /// Subtask this case is in, `0` means the case is scored on its own
+    pub test_group: i64,
+    pub points: i64,
+    /// Samples are shown to everyone under the problem statement
+    pub is_sample: bool,
 }
 
 impl TestCase {
@@ -22,8 +86,12 @@ impl TestCase {
             ord,
             stdin: form.stdin.to_string(),
             expected_pattern: form.expected_pattern.to_string(),
-            use_regex: form.use_regex,
+            compare_mode: form.compare_mode,
             case_insensitive: form.case_insensitive,
+            epsilon: form.epsilon,
+            test_group: form.test_group,
+            points: form.points,
+            is_sample: form.is_sample,
         }
     }
 
@@ -46,10 +114,10 @@ impl TestCase {
             .await?;
         let values_str = cases
             .iter()
-            .map(|_| "(?, ?, ?, ?, ?, ?)")
+            .map(|_| "(?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
             .collect::<Vec<_>>()
             .join(",");
-        let query_str = format!("INSERT OR REPLACE INTO test_case (problem_id, ord, stdin, expected_pattern, use_regex, case_insensitive) VALUES {} RETURNING *", values_str);
+        let query_str = format!("INSERT OR REPLACE INTO test_case (problem_id, ord, stdin, expected_pattern, compare_mode, case_insensitive, epsilon, test_group, points, is_sample) VALUES {} RETURNING *", values_str);
         let mut query = sqlx::query(&query_str);
         for c in cases.iter() {
             query = query
@@ -57,8 +125,12 @@ impl TestCase {
                 .bind(c.ord)
                 .bind(&c.stdin)
                 .bind(&c.expected_pattern)
-                .bind(c.use_regex)
-                .bind(c.case_insensitive);
+                .bind(c.compare_mode)
+                .bind(c.case_insensitive)
+                .bind(c.epsilon)
+                .bind(c.test_group)
+                .bind(c.points)
+                .bind(c.is_sample);
         }
         let res = query.fetch_all(&mut **db).await;
         res.and_then(|rows| {
@@ -81,6 +153,19 @@ impl TestCase {
         .await
     }
 
+    pub async fn get_samples_for_problem(
+        db: &mut DbPoolConnection,
+        problem_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            TestCase,
+            "SELECT * FROM test_case WHERE problem_id = ? AND is_sample = TRUE ORDER BY ord",
+            problem_id
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
     pub async fn count_for_problem(
         db: &mut DbPoolConnection,
         problem_id: i64,
@@ -95,32 +180,91 @@ impl TestCase {
         TestCaseForm {
             stdin: &self.stdin,
             expected_pattern: &self.expected_pattern,
-            use_regex: self.use_regex,
+            compare_mode: self.compare_mode,
             case_insensitive: self.case_insensitive,
+            epsilon: self.epsilon,
+            test_group: self.test_group,
+            points: self.points,
+            is_sample: self.is_sample,
         }
     }
 
-    pub fn check_output(&self, output: &str, expected: &str) -> Result<bool, String> {
-        if self.use_regex {
-            let mut builder = regex::RegexBuilder::new(&self.expected_pattern);
-            builder.case_insensitive(self.case_insensitive);
-            let re = builder
-                .build()
-                .map_err(|e| format!("Couldn't build regex: {e:?}"))?;
-            Ok(re.is_match(output))
-        } else {
-            let (output, expected) = (output.trim(), expected.trim());
-            if self.case_insensitive {
-                Ok(output.to_lowercase() == expected.to_lowercase())
+    /// Total points available for a problem
+    pub fn max_score(cases: &[Self]) -> i64 {
+        cases.iter().map(|c| c.points).sum()
+    }
+
+    /// Points earned given which cases passed. Cases in a group only earn their points if every case in the group passed.
+    pub fn score(cases: &[Self], passed: &[bool]) -> i64 {
+        let mut groups = HashMap::<i64, (i64, bool)>::new();
+        let mut score = 0;
+        for (case, passed) in cases.iter().zip(passed.iter()) {
+            if case.test_group == 0 {
+                if *passed {
+                    score += case.points;
+                }
             } else {
-                Ok(output == expected)
+                let group = groups.entry(case.test_group).or_insert((0, true));
+                group.0 += case.points;
+                group.1 &= *passed;
+            }
+        }
+        score
+            + groups
+                .values()
+                .filter(|(_, all_passed)| *all_passed)
+                .map(|(points, _)| points)
+                .sum::<i64>()
+    }
+
+    fn tokens_equal(&self, output: &str, expected: &str) -> bool {
+        if self.case_insensitive {
+            output.to_lowercase() == expected.to_lowercase()
+        } else {
+            output == expected
+        }
+    }
+
+    /// Numbers match if they're within epsilon of each other, either absolutely or relative to the expected value
+    fn floats_equal(&self, output: &str, expected: &str) -> bool {
+        match (output.parse::<f64>(), expected.parse::<f64>()) {
+            (Ok(out), Ok(exp)) => {
+                let diff = (out - exp).abs();
+                out == exp || diff <= self.epsilon || diff <= self.epsilon * exp.abs()
+            }
+            _ => self.tokens_equal(output, expected),
+        }
+    }
+
+    pub fn check_output(&self, output: &str, expected: &str) -> Result<bool, String> {
+        match self.compare_mode {
+            CompareMode::Regex => {
+                let mut builder = regex::RegexBuilder::new(&self.expected_pattern);
+                builder.case_insensitive(self.case_insensitive);
+                let re = builder
+                    .build()
+                    .map_err(|e| format!("Couldn't build regex: {e:?}"))?;
+                Ok(re.is_match(output))
+            }
+            CompareMode::Exact => Ok(self.tokens_equal(output.trim(), expected.trim())),
+            CompareMode::Tokens | CompareMode::Float => {
+                let output = output.split_whitespace().collect::<Vec<_>>();
+                let expected = expected.split_whitespace().collect::<Vec<_>>();
+                Ok(output.len() == expected.len()
+                    && output.iter().zip(expected.iter()).all(|(o, e)| {
+                        if self.compare_mode == CompareMode::Float {
+                            self.floats_equal(o, e)
+                        } else {
+                            self.tokens_equal(o, e)
+                        }
+                    }))
             }
         }
     }
 }
 
-fn check_regex(pattern: &str, enabled: bool) -> Result<(), rocket::form::Errors> {
-    if enabled {
+fn check_regex<'a>(pattern: &str, mode: &CompareMode) -> Result<(), rocket::form::Errors<'a>> {
+    if *mode == CompareMode::Regex {
         regex::Regex::new(pattern).map(|_| ()).map_err(|e| {
             let err = rocket::form::Error::custom(e);
             rocket::form::Errors::from(vec![err])
@@ -130,14 +274,29 @@ fn check_regex(pattern: &str, enabled: bool) -> Result<(), rocket::form::Errors>
     }
 }
 
+fn check_epsilon<'a>(epsilon: &f64) -> Result<(), rocket::form::Errors<'a>> {
+    if epsilon.is_finite() && *epsilon >= 0.0 {
+        Ok(())
+    } else {
+        Err(rocket::form::Error::validation("Epsilon must be a non-negative number").into())
+    }
+}
+
 #[derive(Debug, FromForm, Serialize)]
 #[form(validate = validate_regex())]
 pub struct TestCaseForm<'r> {
     #[field(validate = len(1..))]
     pub stdin: &'r str,
     #[field(validate = len(1..))]
-    #[field(validate = check_regex(self.use_regex))]
+    #[field(validate = check_regex(&self.compare_mode))]
     pub expected_pattern: &'r str,
-    pub use_regex: bool,
+    pub compare_mode: CompareMode,
     pub case_insensitive: bool,
+    #[field(validate = check_epsilon())]
+    pub epsilon: f64,
+    #[field(validate = range(0..=50))]
+    pub test_group: i64,
+    #[field(validate = range(0..=1000))]
+    pub points: i64,
+    pub is_sample: bool,
 }
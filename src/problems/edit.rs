@@ -3,7 +3,7 @@ use rocket::{
     form::{Contextual, Form},
     get,
     http::Status,
-    post,
+    post, State,
 };
 use rocket_dyn_templates::Template;
 
@@ -13,17 +13,31 @@ use crate::{
         users::User,
     },
     context_with_base_authed,
-    db::DbConnection,
+    db::{DbConnection, DbPoolConnection},
+    run::CodeInfo,
     template::{FormStatus, FormTemplateObject},
 };
 
-use super::{cases::TestCase, Problem, ProblemForm, ProblemFormTemplate};
+use super::{authors::Author, cases::TestCase, Problem, ProblemForm, ProblemFormTemplate};
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Responder)]
 pub enum ProblemEditResponse {
     Form(Template),
     NotFound(Status),
+    Forbidden(Status),
+}
+
+/// The owner and co-authors of a problem, for showing on the edit page
+async fn get_authors(
+    db: &mut DbPoolConnection,
+    problem: &Problem,
+) -> (Option<Author>, Vec<Author>) {
+    let owner = Author::owner(db, problem).await;
+    let co_authors = Author::list_co_authors(db, problem.id)
+        .await
+        .unwrap_or_default();
+    (owner, co_authors)
 }
 
 #[get("/<id>/edit")]
@@ -31,9 +45,15 @@ pub async fn edit_problem_get(
     user: &User,
     mut db: DbConnection,
     id: i64,
+    code_info: &State<CodeInfo>,
     _token: &CsrfToken,
 ) -> ProblemEditResponse {
     if let Some(problem) = Problem::get(&mut db, id).await {
+        if !problem.can_edit(&mut db, user).await {
+            return ProblemEditResponse::Forbidden(Status::Forbidden);
+        }
+        let (owner, co_authors) = get_authors(&mut db, &problem).await;
+        let can_manage_authors = problem.can_manage_authors(user);
         let test_cases = TestCase::get_for_problem(&mut db, problem.id)
             .await
             .unwrap_or_default();
@@ -42,9 +62,10 @@ pub async fn edit_problem_get(
             test_cases: test_cases.iter().map(TestCase::to_form).collect(),
         };
         let form = FormTemplateObject::get(form_template);
+        let languages = code_info.run_config.get_languages_for_dropdown();
         ProblemEditResponse::Form(Template::render(
             "problems/edit",
-            context_with_base_authed!(user, form, problem_name: problem.name, problem_id: problem.id),
+            context_with_base_authed!(user, form, languages, owner, co_authors, can_manage_authors, problem_name: problem.name, problem_id: problem.id),
         ))
     } else {
         ProblemEditResponse::NotFound(Status::NotFound)
@@ -55,11 +76,28 @@ pub async fn edit_problem_get(
 pub async fn edit_problem_post(
     id: i64,
     user: &User,
-    form: Form<Contextual<'_, ProblemForm<'_>>>,
+    mut form: Form<Contextual<'_, ProblemForm<'_>>>,
     _token: &VerifyCsrfToken,
+    code_info: &State<CodeInfo>,
     mut db: DbConnection,
 ) -> ProblemEditResponse {
+    let languages = code_info.run_config.get_languages_for_dropdown();
+
+    if let Some(error) = form
+        .value
+        .as_ref()
+        .and_then(|v| v.validate_checker(&code_info.run_config))
+    {
+        form.context.push_error(error);
+        form.value = None;
+    }
+
     if let Some(mut problem) = Problem::get(&mut db, id).await {
+        if !problem.can_edit(&mut db, user).await {
+            return ProblemEditResponse::Forbidden(Status::Forbidden);
+        }
+        let (owner, co_authors) = get_authors(&mut db, &problem).await;
+        let can_manage_authors = problem.can_manage_authors(user);
         let mut test_cases = TestCase::get_for_problem(&mut db, problem.id)
             .await
             .unwrap_or_default();
@@ -74,11 +112,22 @@ pub async fn edit_problem_post(
             problem.name = value.name.to_string();
             problem.description = value.description.to_string();
             problem.cpu_time = value.cpu_time;
+            problem.memory_limit = value.memory_limit;
+            let checker = value.checker();
+            problem.checker_language = checker.map(|(l, _)| l.to_string());
+            problem.checker_program = checker.map(|(_, p)| p.to_string());
+            problem.interactive = value.interactive;
+            problem.run_all_cases = value.run_all_cases;
             let res = sqlx::query!(
-                "UPDATE problem SET name = ?, description = ?, cpu_time = ? WHERE id = ?",
+                "UPDATE problem SET name = ?, description = ?, cpu_time = ?, memory_limit = ?, checker_language = ?, checker_program = ?, interactive = ?, run_all_cases = ? WHERE id = ?",
                 problem.name,
                 problem.description,
                 problem.cpu_time,
+                problem.memory_limit,
+                problem.checker_language,
+                problem.checker_program,
+                problem.interactive,
+                problem.run_all_cases,
                 problem.id
             )
             .execute(&mut **db)
@@ -105,13 +154,13 @@ pub async fn edit_problem_post(
             form_ctx.status = status;
             ProblemEditResponse::Form(Template::render(
                 "problems/edit",
-                context_with_base_authed!(user, form: form_ctx, problem_name: original_name, problem_id: problem.id),
+                context_with_base_authed!(user, form: form_ctx, languages, owner, co_authors, can_manage_authors, problem_name: original_name, problem_id: problem.id),
             ))
         } else {
             let form_ctx = FormTemplateObject::from_rocket_context(form_template, &form.context);
             ProblemEditResponse::Form(Template::render(
                 "problems/edit",
-                context_with_base_authed!(user, form: form_ctx, problem_name: original_name, problem_id: problem.id),
+                context_with_base_authed!(user, form: form_ctx, languages, owner, co_authors, can_manage_authors, problem_name: original_name, problem_id: problem.id),
             ))
         }
     } else {
@@ -2,8 +2,9 @@
 
 use std::collections::HashMap;
 
-use rocket::{fairing::AdHoc, routes, FromForm};
+use rocket::{fairing::AdHoc, routes, time::OffsetDateTime, FromForm};
 
+mod authors;
 mod cases;
 mod edit;
 mod new;
@@ -11,9 +12,9 @@ mod runs;
 mod view;
 
 pub use cases::TestCase;
-pub use runs::JudgeRun;
+pub use runs::{rejudge_runs, JudgeRun, JudgeRunCase};
 
-use crate::{db::DbPoolConnection, template::TemplatedForm};
+use crate::{db::DbPoolConnection, run::RunConfig, template::TemplatedForm};
 
 use self::cases::TestCaseForm;
 
@@ -23,6 +24,17 @@ pub struct Problem {
     name: String,
     description: String,
     pub cpu_time: i64,
+    pub memory_limit: i64,
+    /// Language of the checker program, if this problem checks output with one
+    pub checker_language: Option<String>,
+    #[serde(skip_serializing)]
+    pub checker_program: Option<String>,
+    /// Whether programs talk to the checker as an interactor instead of being checked after they run
+    pub interactive: bool,
+    /// Keep judging after a case fails so partial scores can be given
+    pub run_all_cases: bool,
+    /// Who made the problem, `None` if they deleted their account
+    pub owner_id: Option<i64>,
 }
 
 impl Problem {
@@ -40,24 +52,53 @@ impl Problem {
             .unwrap()
     }
 
+    /// List problems that aren't part of a contest that hasn't started yet
+    pub async fn list_visible(db: &mut DbPoolConnection) -> Vec<Self> {
+        let now = OffsetDateTime::now_utc();
+        sqlx::query_as!(
+            Problem,
+            "SELECT * FROM problem WHERE id NOT IN (
+                SELECT contest_problem.problem_id FROM contest_problem
+                JOIN contest ON contest.id = contest_problem.contest_id
+                WHERE contest.start_time > ?
+            )",
+            now
+        )
+        .fetch_all(&mut **db)
+        .await
+        .unwrap()
+    }
+
     pub async fn write_to_db(&self, db: &mut DbPoolConnection) -> Result<Problem, sqlx::Error> {
         sqlx::query_as!(
             Problem,
-            "INSERT INTO problem (name, description, cpu_time) VALUES (?, ?, ?) RETURNING *",
+            "INSERT INTO problem (name, description, cpu_time, memory_limit, checker_language, checker_program, interactive, run_all_cases, owner_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
             self.name,
             self.description,
-            self.cpu_time
+            self.cpu_time,
+            self.memory_limit,
+            self.checker_language,
+            self.checker_program,
+            self.interactive,
+            self.run_all_cases,
+            self.owner_id
         )
         .fetch_one(&mut **db)
         .await
     }
 
-    pub fn temp(form: &ProblemForm) -> Self {
+    pub fn temp(form: &ProblemForm, owner_id: i64) -> Self {
         Self {
             id: 0,
             name: form.name.to_string(),
             description: form.description.to_string(),
             cpu_time: form.cpu_time,
+            memory_limit: form.memory_limit,
+            checker_language: form.checker().map(|(l, _)| l.to_string()),
+            checker_program: form.checker().map(|(_, p)| p.to_string()),
+            interactive: form.interactive,
+            run_all_cases: form.run_all_cases,
+            owner_id: Some(owner_id),
         }
     }
 }
@@ -69,10 +110,45 @@ pub struct ProblemForm<'r> {
     description: &'r str,
     #[field(validate = range(1..=100))]
     cpu_time: i64,
+    #[field(validate = range(16..=4096))]
+    memory_limit: i64,
+    checker_language: &'r str,
+    checker_program: &'r str,
+    interactive: bool,
+    run_all_cases: bool,
     #[field(validate = len(..=50))]
     test_cases: Vec<TestCaseForm<'r>>,
 }
 
+impl<'r> ProblemForm<'r> {
+    /// The checker's language and program, if a language was selected
+    pub fn checker(&self) -> Option<(&'r str, &'r str)> {
+        if self.checker_language.is_empty() {
+            None
+        } else {
+            Some((self.checker_language, self.checker_program))
+        }
+    }
+
+    /// Make sure the checker can actually be run, the error is for the field that's wrong
+    pub fn validate_checker<'v>(&self, run_config: &RunConfig) -> Option<rocket::form::Error<'v>> {
+        match self.checker() {
+            Some((language, _)) if !run_config.languages.contains_key(language) => Some(
+                rocket::form::Error::validation("Invalid language").with_name("checker_language"),
+            ),
+            Some((_, program)) if program.trim().is_empty() => Some(
+                rocket::form::Error::validation("Checker program is required")
+                    .with_name("checker_program"),
+            ),
+            None if self.interactive => Some(
+                rocket::form::Error::validation("Interactive problems need an interactor")
+                    .with_name("checker_language"),
+            ),
+            _ => None,
+        }
+    }
+}
+
 pub struct ProblemFormTemplate<'r> {
     problem: Option<&'r Problem>,
     test_cases: Vec<TestCaseForm<'r>>,
@@ -85,6 +161,20 @@ impl<'r> TemplatedForm for ProblemFormTemplate<'r> {
                 ("name".to_string(), problem.name.clone()),
                 ("description".to_string(), problem.description.clone()),
                 ("cpu_time".to_string(), problem.cpu_time.to_string()),
+                ("memory_limit".to_string(), problem.memory_limit.to_string()),
+                (
+                    "checker_language".to_string(),
+                    problem.checker_language.clone().unwrap_or_default(),
+                ),
+                (
+                    "checker_program".to_string(),
+                    problem.checker_program.clone().unwrap_or_default(),
+                ),
+                ("interactive".to_string(), problem.interactive.to_string()),
+                (
+                    "run_all_cases".to_string(),
+                    problem.run_all_cases.to_string(),
+                ),
             ]);
             for (i, case) in self.test_cases.iter().enumerate() {
                 map.insert(format!("test_cases[{}].stdin", i), case.stdin.to_string());
@@ -93,13 +183,26 @@ impl<'r> TemplatedForm for ProblemFormTemplate<'r> {
                     case.expected_pattern.to_string(),
                 );
                 map.insert(
-                    format!("test_cases[{}].use_regex", i),
-                    case.use_regex.to_string(),
+                    format!("test_cases[{}].compare_mode", i),
+                    case.compare_mode.into(),
                 );
                 map.insert(
                     format!("test_cases[{}].case_insensitive", i),
                     case.case_insensitive.to_string(),
                 );
+                map.insert(
+                    format!("test_cases[{}].epsilon", i),
+                    case.epsilon.to_string(),
+                );
+                map.insert(
+                    format!("test_cases[{}].test_group", i),
+                    case.test_group.to_string(),
+                );
+                map.insert(format!("test_cases[{}].points", i), case.points.to_string());
+                map.insert(
+                    format!("test_cases[{}].is_sample", i),
+                    case.is_sample.to_string(),
+                );
             }
             map
         } else {
@@ -107,6 +210,11 @@ impl<'r> TemplatedForm for ProblemFormTemplate<'r> {
                 ("name".to_string(), "".to_string()),
                 ("description".to_string(), "".to_string()),
                 ("cpu_time".to_string(), "1".to_string()),
+                ("memory_limit".to_string(), "256".to_string()),
+                ("checker_language".to_string(), "".to_string()),
+                ("checker_program".to_string(), "".to_string()),
+                ("interactive".to_string(), "false".to_string()),
+                ("run_all_cases".to_string(), "false".to_string()),
             ])
         }
     }
@@ -123,7 +231,12 @@ pub fn stage() -> AdHoc {
                 new::new_problem_post,
                 edit::edit_problem_get,
                 edit::edit_problem_post,
-                runs::runs
+                runs::runs,
+                runs::run_get,
+                runs::rejudge_run_post,
+                runs::rejudge_problem_post,
+                authors::add_author_post,
+                authors::remove_author_post,
             ],
         )
     })
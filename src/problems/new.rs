@@ -3,29 +3,39 @@ use rocket::{
     form::{Contextual, Form},
     get, post,
     response::Redirect,
+    State,
 };
 use rocket_dyn_templates::Template;
 
 use crate::{
     auth::{
         csrf::{CsrfToken, VerifyCsrfToken},
-        users::User,
+        users::ProblemSetter,
     },
     context_with_base_authed,
     db::DbConnection,
+    run::CodeInfo,
     template::FormTemplateObject,
 };
 
 use super::{cases::TestCase, Problem, ProblemForm, ProblemFormTemplate};
 
 #[get("/new", rank = 5)]
-pub fn new_problem_get(user: &User, _token: &CsrfToken) -> Template {
+pub fn new_problem_get(
+    user: &ProblemSetter,
+    code_info: &State<CodeInfo>,
+    _token: &CsrfToken,
+) -> Template {
     let form_template = ProblemFormTemplate {
         problem: None,
         test_cases: vec![],
     };
     let form = FormTemplateObject::get(form_template);
-    Template::render("problems/new", context_with_base_authed!(user, form))
+    let languages = code_info.run_config.get_languages_for_dropdown();
+    Template::render(
+        "problems/new",
+        context_with_base_authed!(user, form, languages),
+    )
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -37,13 +47,23 @@ pub enum ProblemNewResponse {
 
 #[post("/new", data = "<form>", rank = 5)]
 pub async fn new_problem_post(
-    user: &User,
-    form: Form<Contextual<'_, ProblemForm<'_>>>,
+    user: &ProblemSetter,
+    mut form: Form<Contextual<'_, ProblemForm<'_>>>,
     _token: &VerifyCsrfToken,
+    code_info: &State<CodeInfo>,
     mut db: DbConnection,
 ) -> ProblemNewResponse {
+    if let Some(error) = form
+        .value
+        .as_ref()
+        .and_then(|v| v.validate_checker(&code_info.run_config))
+    {
+        form.context.push_error(error);
+        form.value = None;
+    }
+
     if let Some(ref value) = form.value {
-        let problem = Problem::temp(value);
+        let problem = Problem::temp(value, user.id);
         let res = problem.write_to_db(&mut db).await;
         match res {
             Ok(problem) => {
@@ -64,10 +84,11 @@ pub async fn new_problem_post(
             test_cases: vec![],
         };
         let form = FormTemplateObject::from_rocket_context(form_template, &form.context);
+        let languages = code_info.run_config.get_languages_for_dropdown();
 
         ProblemNewResponse::Error(Template::render(
             "problems/new",
-            context_with_base_authed!(user, form),
+            context_with_base_authed!(user, form, languages),
         ))
     }
 }
@@ -1,14 +1,22 @@
-use rocket::get;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use log::{error, info};
 use rocket::http::Status;
+use rocket::response::Redirect;
 use rocket::time::OffsetDateTime;
+use rocket::{get, post, State};
 use rocket_dyn_templates::Template;
+use sqlx::Connection;
 
-use crate::auth::users::User;
+use crate::auth::csrf::{CsrfToken, VerifyCsrfToken};
+use crate::auth::users::{Judge, User};
+use crate::contests::{Contest, ContestProblem};
 use crate::context_with_base_authed;
 use crate::db::{DbConnection, DbPoolConnection};
-use crate::run::JobState;
+use crate::run::{JobResult, JobState, ManagerHandle, Verdict};
 
-use super::Problem;
+use super::{Problem, TestCase};
 
 #[derive(Serialize)]
 pub struct JudgeRun {
@@ -18,47 +26,48 @@ pub struct JudgeRun {
     pub amount_run: i64,
     pub total_cases: i64,
     pub error: Option<String>,
-    #[serde(skip)]
+    /// Short code of the verdict that failed the run, missing if it passed
+    pub verdict: Option<String>,
+    pub score: i64,
+    pub max_score: i64,
+    pub program: String,
+    pub language: String,
+    /// What the compiler printed if the program failed to compile
+    pub compile_output: Option<String>,
+    /// When the run was submitted, not judged, so contest penalties and cutoffs don't depend on the queue
+    #[serde(with = "time::serde::rfc3339")]
     pub ran_at: OffsetDateTime,
 }
 
 impl JudgeRun {
-    pub fn temp(
+    pub fn from_job_result(
         problem_id: i64,
         user_id: i64,
-        amount_run: i64,
-        total_cases: i64,
-        error: Option<String>,
+        program: String,
+        language: String,
+        result: &JobResult,
+        cases: &[TestCase],
         ran_at: OffsetDateTime,
     ) -> Self {
+        let (amount_run, error) = result.state.last_error();
+        let compile_output = result.state.compile_output();
         Self {
             id: 0,
             problem_id,
             user_id,
-            amount_run,
-            total_cases,
+            amount_run: amount_run as i64,
+            total_cases: result.state.len() as i64,
             error,
+            verdict: result.state.verdict().map(|v| v.code().to_string()),
+            score: TestCase::score(cases, &result.state.passed()),
+            max_score: TestCase::max_score(cases),
+            program,
+            language,
+            compile_output,
             ran_at,
         }
     }
 
-    pub fn from_job_state(
-        problem_id: i64,
-        user_id: i64,
-        state: JobState,
-        ran_at: OffsetDateTime,
-    ) -> Self {
-        let (amount_run, error) = state.last_error();
-        Self::temp(
-            problem_id,
-            user_id,
-            amount_run as i64,
-            state.len() as i64,
-            error,
-            ran_at,
-        )
-    }
-
     pub async fn list(
         db: &mut DbPoolConnection,
         user_id: i64,
@@ -74,6 +83,33 @@ impl JudgeRun {
         .await
     }
 
+    pub async fn get(
+        db: &mut DbPoolConnection,
+        id: i64,
+        problem_id: i64,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            JudgeRun,
+            "SELECT * FROM judge_run WHERE id = ? AND problem_id = ?",
+            id,
+            problem_id
+        )
+        .fetch_optional(&mut **db)
+        .await
+    }
+
+    /// Runs can only be looked at by the person who submitted them and judges
+    pub fn can_view(&self, user: &User) -> bool {
+        self.user_id == user.id || user.role.can_judge()
+    }
+
+    /// Cancelled runs were never meant to be judged, and runs from before programs were saved have nothing to judge
+    pub fn can_rejudge(&self) -> bool {
+        self.verdict.as_deref() != Some(Verdict::Cancelled.code())
+            && !self.program.is_empty()
+            && !self.language.is_empty()
+    }
+
     pub async fn get_latest(
         db: &mut DbPoolConnection,
         user_id: i64,
@@ -89,15 +125,83 @@ impl JudgeRun {
             .await
     }
 
+    pub async fn list_for_problem(
+        db: &mut DbPoolConnection,
+        problem_id: i64,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            JudgeRun,
+            "SELECT * FROM judge_run WHERE problem_id = ? ORDER BY ran_at",
+            problem_id
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Runs made by participants of a contest on its problems while it was running
+    pub async fn list_for_contest(
+        db: &mut DbPoolConnection,
+        contest: &Contest,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            JudgeRun,
+            r#"SELECT judge_run.* FROM judge_run
+            JOIN contest_problem ON contest_problem.problem_id = judge_run.problem_id AND contest_problem.contest_id = ?
+            JOIN participant ON participant.user_id = judge_run.user_id AND participant.contest_id = ?
+            WHERE judge_run.ran_at >= ? AND judge_run.ran_at <= ?
+            ORDER BY judge_run.ran_at"#,
+            contest.id,
+            contest.id,
+            contest.start_time,
+            contest.end_time
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    /// Replace the verdict of an existing run after it's been judged again, keeping when it was submitted
+    pub async fn update_verdict(
+        self,
+        db: &mut DbPoolConnection,
+        run_id: i64,
+    ) -> Result<Self, sqlx::Error> {
+        // The old cases and verdict are replaced together so a failed update can't leave a run without its cases
+        let mut tx = db.begin().await?;
+        JudgeRunCase::delete_for_run(&mut tx, run_id).await?;
+        sqlx::query!(
+            "UPDATE judge_run SET amount_run = ?, total_cases = ?, error = ?, verdict = ?, score = ?, max_score = ?, compile_output = ? WHERE id = ?",
+            self.amount_run,
+            self.total_cases,
+            self.error,
+            self.verdict,
+            self.score,
+            self.max_score,
+            self.compile_output,
+            run_id
+        )
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Self::get(db, run_id, self.problem_id)
+            .await?
+            .ok_or(sqlx::Error::RowNotFound)
+    }
+
     pub async fn write_to_db(self, db: &mut DbPoolConnection) -> Result<Self, sqlx::Error> {
         sqlx::query_as!(
             JudgeRun,
-            "INSERT INTO judge_run (problem_id, user_id, amount_run, total_cases, error, ran_at) VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+            "INSERT INTO judge_run (problem_id, user_id, amount_run, total_cases, error, verdict, score, max_score, program, language, compile_output, ran_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) RETURNING *",
             self.problem_id,
             self.user_id,
             self.amount_run,
             self.total_cases,
             self.error,
+            self.verdict,
+            self.score,
+            self.max_score,
+            self.program,
+            self.language,
+            self.compile_output,
             self.ran_at
         )
             .fetch_one(&mut **db)
@@ -105,15 +209,113 @@ impl JudgeRun {
     }
 }
 
+/// The result of a single test case in a judge run
+#[derive(Serialize)]
+pub struct JudgeRunCase {
+    pub id: i64,
+    pub run_id: i64,
+    pub case_index: i64,
+    pub status: String,
+    /// Short code of the verdict, missing if the case didn't finish
+    pub verdict: Option<String>,
+    pub message: Option<String>,
+    pub exit_code: Option<i64>,
+    pub signal: Option<i64>,
+    pub cpu_time_ms: Option<i64>,
+    pub wall_time_ms: Option<i64>,
+    pub memory_kb: Option<i64>,
+}
+
+impl JudgeRunCase {
+    pub fn from_job_result(result: &JobResult) -> Vec<Self> {
+        let cases = match &result.state {
+            JobState::Judging { cases, .. } => cases.as_slice(),
+            _ => &[],
+        };
+        cases
+            .iter()
+            .enumerate()
+            .map(|(i, status)| {
+                let report = status.report();
+                let usage = report.and_then(|r| r.usage);
+                Self {
+                    id: 0,
+                    run_id: 0,
+                    case_index: i as i64,
+                    status: status.to_name().to_string(),
+                    verdict: report.map(|r| r.verdict.code().to_string()),
+                    message: report.and_then(|r| r.message.clone()),
+                    exit_code: report.and_then(|r| r.exit_code).map(i64::from),
+                    signal: report.and_then(|r| r.signal).map(i64::from),
+                    cpu_time_ms: usage.map(|u| u.cpu_time_ms),
+                    wall_time_ms: usage.map(|u| u.wall_time_ms),
+                    memory_kb: usage.map(|u| u.memory_kb),
+                }
+            })
+            .collect()
+    }
+
+    pub async fn list(db: &mut DbPoolConnection, run_id: i64) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            JudgeRunCase,
+            "SELECT * FROM judge_run_case WHERE run_id = ? ORDER BY case_index",
+            run_id
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    pub async fn delete_for_run(
+        db: &mut sqlx::SqliteConnection,
+        run_id: i64,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM judge_run_case WHERE run_id = ?", run_id)
+            .execute(db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn write_all(
+        db: &mut DbPoolConnection,
+        run_id: i64,
+        cases: Vec<Self>,
+    ) -> Result<(), sqlx::Error> {
+        for case in cases {
+            sqlx::query!(
+                "INSERT INTO judge_run_case (run_id, case_index, status, verdict, message, exit_code, signal, cpu_time_ms, wall_time_ms, memory_kb) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+                run_id,
+                case.case_index,
+                case.status,
+                case.verdict,
+                case.message,
+                case.exit_code,
+                case.signal,
+                case.cpu_time_ms,
+                case.wall_time_ms,
+                case.memory_kb
+            )
+            .execute(&mut **db)
+            .await?;
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::large_enum_variant)]
 #[derive(Responder)]
 pub enum RunsResponse {
     NotFound(Status),
+    Forbidden(Status),
+    Error(Status),
     Ok(Template),
 }
 
 #[get("/<id>/runs")]
 pub async fn runs(id: i64, user: &User, mut db: DbConnection) -> RunsResponse {
     if let Some(problem) = Problem::get(&mut db, id).await {
+        if ContestProblem::is_hidden(&mut db, &problem, Some(user)).await {
+            return RunsResponse::NotFound(Status::NotFound);
+        }
         let runs = JudgeRun::list(&mut db, user.id, problem.id).await.unwrap();
         RunsResponse::Ok(Template::render(
             "problems/runs",
@@ -123,3 +325,137 @@ pub async fn runs(id: i64, user: &User, mut db: DbConnection) -> RunsResponse {
         RunsResponse::NotFound(Status::NotFound)
     }
 }
+
+#[get("/<id>/runs/<run_id>")]
+pub async fn run_get(
+    id: i64,
+    run_id: i64,
+    user: &User,
+    mut db: DbConnection,
+    _token: &CsrfToken,
+) -> RunsResponse {
+    let problem = match Problem::get(&mut db, id).await {
+        Some(problem) => problem,
+        None => return RunsResponse::NotFound(Status::NotFound),
+    };
+    if ContestProblem::is_hidden(&mut db, &problem, Some(user)).await {
+        return RunsResponse::NotFound(Status::NotFound);
+    }
+    let run = match JudgeRun::get(&mut db, run_id, problem.id).await {
+        Ok(Some(run)) => run,
+        Ok(None) => return RunsResponse::NotFound(Status::NotFound),
+        Err(why) => {
+            error!("Couldn't get run {}: {:?}", run_id, why);
+            return RunsResponse::Error(Status::InternalServerError);
+        }
+    };
+    if !run.can_view(user) {
+        return RunsResponse::Forbidden(Status::Forbidden);
+    }
+    let cases = JudgeRunCase::list(&mut db, run.id)
+        .await
+        .unwrap_or_default();
+    let submitter = sqlx::query!(
+        r#"SELECT COALESCE(display_name, default_display_name) AS "display_name!: String" FROM user WHERE id = ?"#,
+        run.user_id
+    )
+    .fetch_optional(&mut **db)
+    .await
+    .ok()
+    .flatten()
+    .map(|u| u.display_name);
+    let can_rejudge = run.can_rejudge();
+    RunsResponse::Ok(Template::render(
+        "problems/run",
+        context_with_base_authed!(user, run, cases, problem, submitter, can_rejudge),
+    ))
+}
+
+/// Queue runs to be judged again with their problem's current test cases, returns how many were queued.
+/// Runs that can't be rejudged are skipped.
+pub async fn rejudge_runs(
+    db: &mut DbPoolConnection,
+    manager: &ManagerHandle,
+    runs: Vec<JudgeRun>,
+) -> usize {
+    let mut problems: HashMap<i64, Option<(Problem, Vec<TestCase>)>> = HashMap::new();
+    let mut manager = manager.lock().await;
+    let mut queued = 0;
+    for run in runs.into_iter().filter(JudgeRun::can_rejudge) {
+        let problem = match problems.entry(run.problem_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let problem = match Problem::get(db, run.problem_id).await {
+                    Some(problem) => TestCase::get_for_problem(db, problem.id)
+                        .await
+                        .ok()
+                        .filter(|cases| !cases.is_empty())
+                        .map(|cases| (problem, cases)),
+                    None => None,
+                };
+                entry.insert(problem)
+            }
+        };
+        if let Some((problem, cases)) = problem {
+            match manager.request_rejudge(problem, cases.clone(), &run).await {
+                Ok(_) => queued += 1,
+                Err(why) => error!("Couldn't rejudge run {}: {}", run.id, why),
+            }
+        }
+    }
+    queued
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Responder)]
+pub enum RejudgeResponse {
+    Redirect(Redirect),
+    Error(Status),
+}
+
+#[post("/<id>/runs/<run_id>/rejudge")]
+pub async fn rejudge_run_post(
+    id: i64,
+    run_id: i64,
+    _user: &Judge,
+    _token: &VerifyCsrfToken,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+) -> RejudgeResponse {
+    match JudgeRun::get(&mut db, run_id, id).await {
+        Ok(Some(run)) if !run.can_rejudge() => RejudgeResponse::Error(Status::BadRequest),
+        Ok(Some(run)) => {
+            rejudge_runs(&mut db, manager, vec![run]).await;
+            RejudgeResponse::Redirect(Redirect::to(format!("/problems/{id}/runs/{run_id}")))
+        }
+        Ok(None) => RejudgeResponse::Error(Status::NotFound),
+        Err(why) => {
+            error!("Couldn't get run {}: {:?}", run_id, why);
+            RejudgeResponse::Error(Status::InternalServerError)
+        }
+    }
+}
+
+#[post("/<id>/rejudge")]
+pub async fn rejudge_problem_post(
+    id: i64,
+    _user: &Judge,
+    _token: &VerifyCsrfToken,
+    manager: &State<ManagerHandle>,
+    mut db: DbConnection,
+) -> RejudgeResponse {
+    if Problem::get(&mut db, id).await.is_none() {
+        return RejudgeResponse::Error(Status::NotFound);
+    }
+    match JudgeRun::list_for_problem(&mut db, id).await {
+        Ok(runs) => {
+            let queued = rejudge_runs(&mut db, manager, runs).await;
+            info!("Queued {} runs of problem {} to be rejudged", queued, id);
+            RejudgeResponse::Redirect(Redirect::to(format!("/problems/{id}")))
+        }
+        Err(why) => {
+            error!("Couldn't list runs for problem {}: {:?}", id, why);
+            RejudgeResponse::Error(Status::InternalServerError)
+        }
+    }
+}
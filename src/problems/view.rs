@@ -1,7 +1,13 @@
 use rocket::{get, http::Status, State};
 use rocket_dyn_templates::Template;
 
-use crate::{auth::users::User, context_with_base, db::DbConnection, run::CodeInfo};
+use crate::{
+    auth::{csrf::CsrfToken, users::User},
+    contests::ContestProblem,
+    context_with_base,
+    db::DbConnection,
+    run::CodeInfo,
+};
 
 use super::{JudgeRun, Problem, TestCase};
 
@@ -13,7 +19,7 @@ pub enum ProblemViewResponse {
 
 #[get("/")]
 pub async fn list_problems_get(user: Option<&User>, mut db: DbConnection) -> Template {
-    let problems = Problem::list(&mut db).await;
+    let problems = Problem::list_visible(&mut db).await;
     Template::render("problems", context_with_base!(user, problems))
 }
 
@@ -22,9 +28,14 @@ pub async fn view_problem_get(
     user: Option<&User>,
     info: &State<CodeInfo>,
     mut db: DbConnection,
+    _token: &CsrfToken,
     id: i64,
 ) -> ProblemViewResponse {
     if let Some(problem) = Problem::get(&mut db, id).await {
+        if ContestProblem::is_hidden(&mut db, &problem, user).await {
+            return ProblemViewResponse::NotFound(Status::NotFound);
+        }
+
         let last_run = if let Some(user) = user {
             JudgeRun::get_latest(&mut db, user.id, problem.id)
                 .await
@@ -38,6 +49,10 @@ pub async fn view_problem_get(
             .await
             .unwrap_or(0);
 
+        let samples = TestCase::get_samples_for_problem(&mut db, problem.id)
+            .await
+            .unwrap_or_default();
+
         let languages = info.run_config.get_languages_for_dropdown();
         let code_info = &info.languages_json;
         let default_language = user
@@ -52,6 +67,7 @@ pub async fn view_problem_get(
                 problem,
                 last_run,
                 case_count,
+                samples,
                 code_info,
                 languages,
                 default_language
@@ -0,0 +1,108 @@
+use std::{
+    fs::File,
+    os::fd::{AsRawFd, RawFd},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+/// Used to give every cgroup this server creates a unique name
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// How long to wait for a killed cgroup to empty before giving up on removing it
+const REMOVE_ATTEMPTS: usize = 100;
+const REMOVE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// A cgroup v2 group a single process (and everything it starts) is run in.
+/// Unlike a process group, a program can't leave its cgroup, so killing it is guaranteed to get everything.
+pub struct Cgroup {
+    path: PathBuf,
+    /// Kept open so the process can join by writing to it before it execs
+    procs: File,
+}
+
+impl Cgroup {
+    /// Create a new cgroup under `root`, which must be a cgroup v2 directory the server can write to
+    pub fn create(root: &Path) -> std::io::Result<Self> {
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let path = root.join(format!("wcpc_{}_{id}", std::process::id()));
+        std::fs::create_dir(&path)?;
+        let procs = std::fs::OpenOptions::new()
+            .write(true)
+            .open(path.join("cgroup.procs"));
+        match procs {
+            Ok(procs) => Ok(Self { path, procs }),
+            Err(e) => {
+                std::fs::remove_dir(&path).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// File descriptor of `cgroup.procs`, writing "0" to it moves the calling process into this cgroup
+    pub fn procs_fd(&self) -> RawFd {
+        self.procs.as_raw_fd()
+    }
+
+    /// Cap the memory of everything in this cgroup, going over it gets the whole group OOM killed
+    pub fn limit_memory(&self, bytes: u64) -> std::io::Result<()> {
+        std::fs::write(self.path.join("memory.max"), bytes.to_string())?;
+        std::fs::write(self.path.join("memory.oom.group"), "1")?;
+        // Swap might not be enabled, in which case this file doesn't exist
+        std::fs::write(self.path.join("memory.swap.max"), "0").ok();
+        Ok(())
+    }
+
+    /// Whether anything in this cgroup was killed for going over its memory limit
+    pub fn oom_killed(&self) -> bool {
+        std::fs::read_to_string(self.path.join("memory.events"))
+            .ok()
+            .and_then(|events| {
+                events
+                    .lines()
+                    .find_map(|l| l.strip_prefix("oom_kill "))
+                    .and_then(|n| n.trim().parse::<u64>().ok())
+            })
+            .is_some_and(|n| n > 0)
+    }
+
+    /// Current memory usage of this cgroup in bytes
+    pub fn current_memory(&self) -> Option<u64> {
+        self.read_u64("memory.current")
+    }
+
+    /// Peak memory usage of this cgroup in bytes, this isn't available before Linux 5.19
+    pub fn peak_memory(&self) -> Option<u64> {
+        self.read_u64("memory.peak")
+    }
+
+    fn read_u64(&self, file: &str) -> Option<u64> {
+        std::fs::read_to_string(self.path.join(file))
+            .ok()
+            .and_then(|s| s.trim().parse().ok())
+    }
+
+    /// Kill every process in this cgroup
+    pub fn kill(&self) {
+        std::fs::write(self.path.join("cgroup.kill"), "1").ok();
+    }
+
+    /// Kill everything in this cgroup and remove it once it's empty
+    pub async fn remove(self) {
+        self.kill();
+        for _ in 0..REMOVE_ATTEMPTS {
+            if std::fs::remove_dir(&self.path).is_ok() {
+                return;
+            }
+            tokio::time::sleep(REMOVE_INTERVAL).await;
+        }
+    }
+}
+
+impl Drop for Cgroup {
+    fn drop(&mut self) {
+        // Best effort for when a process couldn't be supervised, this will fail if it was already removed
+        self.kill();
+        std::fs::remove_dir(&self.path).ok();
+    }
+}
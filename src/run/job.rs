@@ -1,22 +1,163 @@
 use log::{error, info};
-use rocket::time::OffsetDateTime;
 
-use crate::{problems::TestCase, run::runner::CaseError};
+use crate::{
+    problems::{Problem, TestCase},
+    run::runner::CaseError,
+};
 
 use super::{
-    languages::LanguageConfig, manager::ShutdownReceiver, runner::Runner, JobStateReceiver,
+    languages::RunConfig,
+    manager::{CancelReceiver, ShutdownReceiver},
+    runner::Runner,
     JobStateSender,
 };
 
+/// The outcome of a case that finished running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Verdict {
+    #[serde(rename = "AC")]
+    Accepted,
+    #[serde(rename = "WA")]
+    WrongAnswer,
+    #[serde(rename = "TLE")]
+    TimeLimitExceeded,
+    #[serde(rename = "MLE")]
+    MemoryLimitExceeded,
+    #[serde(rename = "RE")]
+    RuntimeError,
+    #[serde(rename = "CE")]
+    CompileError,
+    #[serde(rename = "CTLE")]
+    CompileTimeLimitExceeded,
+    #[serde(rename = "OLE")]
+    OutputLimitExceeded,
+    #[serde(rename = "JE")]
+    JudgeError,
+    #[serde(rename = "CAN")]
+    Cancelled,
+}
+
+impl Verdict {
+    /// Short code for the verdict, this is also how it's saved
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Accepted => "AC",
+            Self::WrongAnswer => "WA",
+            Self::TimeLimitExceeded => "TLE",
+            Self::MemoryLimitExceeded => "MLE",
+            Self::RuntimeError => "RE",
+            Self::CompileError => "CE",
+            Self::CompileTimeLimitExceeded => "CTLE",
+            Self::OutputLimitExceeded => "OLE",
+            Self::JudgeError => "JE",
+            Self::Cancelled => "CAN",
+        }
+    }
+
+    // Keep in sync with the names in the frontend
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Accepted => "Passed",
+            Self::WrongAnswer => "Logic error",
+            Self::TimeLimitExceeded => "Time limit exceeded",
+            Self::MemoryLimitExceeded => "Memory limit exceeded",
+            Self::RuntimeError => "Runtime error",
+            Self::CompileError => "Compile error",
+            Self::CompileTimeLimitExceeded => "Compilation timed out",
+            Self::OutputLimitExceeded => "Output limit exceeded",
+            Self::JudgeError => "Judge error",
+            Self::Cancelled => "Cancelled",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "AC" => Some(Self::Accepted),
+            "WA" => Some(Self::WrongAnswer),
+            "TLE" => Some(Self::TimeLimitExceeded),
+            "MLE" => Some(Self::MemoryLimitExceeded),
+            "RE" => Some(Self::RuntimeError),
+            "CE" => Some(Self::CompileError),
+            "CTLE" => Some(Self::CompileTimeLimitExceeded),
+            "OLE" => Some(Self::OutputLimitExceeded),
+            "JE" => Some(Self::JudgeError),
+            "CAN" => Some(Self::Cancelled),
+            _ => None,
+        }
+    }
+
+    /// Whether a rejected run with this verdict counts as an attempt in contests. Programs that never
+    /// got to run don't (as in ICPC rules), and neither do failures on our end or cancelled runs.
+    pub fn is_penalised(&self) -> bool {
+        !matches!(
+            self,
+            Self::CompileError
+                | Self::CompileTimeLimitExceeded
+                | Self::JudgeError
+                | Self::Cancelled
+        )
+    }
+}
+
+/// Resources a program used while running a case
+#[derive(Debug, Clone, Copy, Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceUsage {
+    pub cpu_time_ms: i64,
+    pub wall_time_ms: i64,
+    /// Peak resident memory
+    pub memory_kb: i64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseReport {
+    pub verdict: Verdict,
+    /// Output of the program when testing, otherwise details about the verdict that are safe to show
+    pub message: Option<String>,
+    /// Only set for runtime errors where the program exited on its own
+    pub exit_code: Option<i32>,
+    /// Only set for runtime errors where the program was killed by a signal
+    pub signal: Option<i32>,
+    /// Missing if the program never ran, like when it failed to compile
+    pub usage: Option<ResourceUsage>,
+}
+
+impl CaseReport {
+    pub fn new(verdict: Verdict, message: Option<String>, usage: Option<ResourceUsage>) -> Self {
+        Self {
+            verdict,
+            message,
+            exit_code: None,
+            signal: None,
+            usage,
+        }
+    }
+
+    /// Short description of the verdict, this is what's saved as a run's error
+    pub fn describe(&self) -> String {
+        let mut desc = self.verdict.name().to_string();
+        match (self.exit_code, self.signal) {
+            (_, Some(signal)) => desc.push_str(&format!(" (signal {signal})")),
+            (Some(code), None) => desc.push_str(&format!(" (exit code {code})")),
+            (None, None) => {}
+        }
+        if let (Verdict::WrongAnswer, Some(msg)) = (self.verdict, &self.message) {
+            desc.push_str(&format!(": {msg}"));
+        }
+        desc
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Default)]
 #[serde(tag = "status", content = "content", rename_all = "camelCase")]
 pub enum CaseStatus {
     #[default]
     Pending,
     Running,
-    Passed(Option<String>),
+    Passed(CaseReport),
     NotRun,
-    Failed(String),
+    Failed(CaseReport),
 }
 
 impl CaseStatus {
@@ -29,14 +170,28 @@ impl CaseStatus {
             Self::Failed(_) => "Failed",
         }
     }
+
+    pub fn report(&self) -> Option<&CaseReport> {
+        match self {
+            Self::Passed(report) | Self::Failed(report) => Some(report),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Clone)]
 #[serde(tag = "type", rename_all = "camelCase")]
 pub enum JobState {
+    Queued {
+        position: usize,
+    },
     Judging {
         cases: Vec<CaseStatus>,
         complete: bool,
+        /// Keep going after a case fails instead of skipping the rest
+        run_all: bool,
+        /// Only the problem's samples are being run, the result isn't saved
+        samples: bool,
     },
     Testing {
         status: CaseStatus,
@@ -44,10 +199,22 @@ pub enum JobState {
 }
 
 impl JobState {
-    pub fn new_judging(cases: usize) -> Self {
+    pub fn new_judging(cases: usize, run_all: bool) -> Self {
+        Self::Judging {
+            cases: vec![CaseStatus::Pending; cases],
+            complete: false,
+            run_all,
+            samples: false,
+        }
+    }
+
+    /// Samples always run every case so all of their results are shown
+    pub fn new_samples(cases: usize) -> Self {
         Self::Judging {
             cases: vec![CaseStatus::Pending; cases],
             complete: false,
+            run_all: true,
+            samples: true,
         }
     }
 
@@ -57,22 +224,38 @@ impl JobState {
         }
     }
 
+    /// Verdict of the first case that failed, if any did
+    pub fn verdict(&self) -> Option<Verdict> {
+        match self {
+            Self::Judging { cases, .. } => cases.iter().find_map(|c| match c {
+                CaseStatus::Failed(report) => Some(report.verdict),
+                _ => None,
+            }),
+            Self::Queued { .. } => None,
+            Self::Testing { status } => match status {
+                CaseStatus::Failed(report) => Some(report.verdict),
+                _ => None,
+            },
+        }
+    }
+
     pub fn last_error(&self) -> (usize, Option<String>) {
         match self {
             Self::Judging { cases, .. } => cases
                 .iter()
                 .enumerate()
                 .find_map(|(i, c)| {
-                    if let CaseStatus::Failed(e) = c {
-                        Some((i, Some(e.clone())))
+                    if let CaseStatus::Failed(report) = c {
+                        Some((i, Some(report.describe())))
                     } else {
                         None
                     }
                 })
                 .unwrap_or_else(|| (self.len(), None)),
+            Self::Queued { .. } => (0, None),
             Self::Testing { status } => {
-                if let CaseStatus::Failed(e) = status {
-                    (0, Some(e.clone()))
+                if let CaseStatus::Failed(report) = status {
+                    (0, Some(report.describe()))
                 } else {
                     (0, None)
                 }
@@ -80,8 +263,33 @@ impl JobState {
         }
     }
 
+    /// What the compiler printed if the program failed to compile
+    pub fn compile_output(&self) -> Option<String> {
+        let first = match self {
+            Self::Judging { cases, .. } => cases.first(),
+            Self::Testing { status } => Some(status),
+            Self::Queued { .. } => None,
+        };
+        first
+            .and_then(CaseStatus::report)
+            .filter(|r| r.verdict == Verdict::CompileError)
+            .and_then(|r| r.message.clone())
+    }
+
+    /// Which cases passed, for scoring
+    pub fn passed(&self) -> Vec<bool> {
+        match self {
+            Self::Judging { cases, .. } => cases
+                .iter()
+                .map(|c| matches!(c, CaseStatus::Passed(_)))
+                .collect(),
+            _ => vec![],
+        }
+    }
+
     pub fn len(&self) -> usize {
         match self {
+            Self::Queued { .. } => 0,
             Self::Judging { cases, .. } => cases.len(),
             Self::Testing { .. } => 1,
         }
@@ -89,6 +297,7 @@ impl JobState {
 
     pub fn complete(&self) -> bool {
         match self {
+            Self::Queued { .. } => false,
             Self::Judging { complete, .. } => *complete,
             Self::Testing { status } => matches!(
                 status,
@@ -99,6 +308,7 @@ impl JobState {
 
     pub fn start_first(&mut self) {
         match self {
+            Self::Queued { .. } => {}
             Self::Judging { cases, .. } => {
                 cases[0] = CaseStatus::Running;
             }
@@ -108,12 +318,38 @@ impl JobState {
         }
     }
 
+    /// Stop at a case without running the ones after it, used when the job can't continue
+    pub fn stop_at(&mut self, idx: usize, status: CaseStatus) {
+        match self {
+            Self::Queued { .. } => {}
+            Self::Judging {
+                cases, complete, ..
+            } => {
+                cases
+                    .iter_mut()
+                    .skip(idx + 1)
+                    .for_each(|c| *c = CaseStatus::NotRun);
+                cases[idx] = status;
+                *complete = true;
+            }
+            Self::Testing { status: my_status } => {
+                *my_status = status;
+            }
+        }
+    }
+
     pub fn complete_case(&mut self, idx: usize, status: CaseStatus) {
         match self {
-            Self::Judging { cases, complete } => {
+            Self::Queued { .. } => {}
+            Self::Judging {
+                cases,
+                complete,
+                run_all,
+                ..
+            } => {
                 if idx == cases.len() - 1 {
                     *complete = true;
-                } else if matches!(&status, CaseStatus::Failed(_)) {
+                } else if !*run_all && matches!(&status, CaseStatus::Failed(_)) {
                     cases
                         .iter_mut()
                         .skip(idx + 1)
@@ -133,26 +369,88 @@ impl JobState {
 
 pub enum JobOperation {
     Judging(Vec<TestCase>),
+    /// Judging where the program talks to the problem's interactor instead of reading each case's input
+    Interactive(Vec<TestCase>),
     Testing(String),
 }
 
+/// A program that decides if the output for a case is correct, used instead of the case's expected pattern.
+/// For interactive problems this is the interactor.
+pub struct CheckerProgram {
+    pub language: String,
+    pub program: String,
+}
+
+impl CheckerProgram {
+    pub fn for_problem(problem: &Problem) -> Option<Self> {
+        problem
+            .checker_language
+            .clone()
+            .zip(problem.checker_program.clone())
+            .map(|(language, program)| Self { language, program })
+    }
+}
+
 pub struct JobRequest {
     pub user_id: i64,
     pub problem_id: i64,
     pub program: String,
     pub language: String,
     pub cpu_time: i64,
+    pub memory_limit: i64,
+    /// Only used when judging
+    pub checker: Option<CheckerProgram>,
+    /// Only used when judging
+    pub run_all_cases: bool,
+    /// Only the problem's samples are being judged, so the result shouldn't be saved
+    pub samples_only: bool,
+    /// The judge run this job is re-judging, its verdict is replaced instead of adding a new run
+    pub rejudge_run_id: Option<i64>,
     pub op: JobOperation,
 }
 
+impl JobRequest {
+    /// Judge a program against all of a problem's test cases
+    pub fn judge(
+        problem: &Problem,
+        cases: Vec<TestCase>,
+        user_id: i64,
+        program: String,
+        language: String,
+    ) -> Self {
+        Self {
+            user_id,
+            problem_id: problem.id,
+            program,
+            language,
+            cpu_time: problem.cpu_time,
+            memory_limit: problem.memory_limit,
+            checker: CheckerProgram::for_problem(problem),
+            run_all_cases: problem.run_all_cases,
+            samples_only: false,
+            rejudge_run_id: None,
+            op: if problem.interactive {
+                JobOperation::Interactive(cases)
+            } else {
+                JobOperation::Judging(cases)
+            },
+        }
+    }
+}
+
+/// What a job ended up doing, returned once it's done running
+pub struct JobResult {
+    pub state: JobState,
+}
+
 pub struct Job {
     pub id: u64,
     user_id: i64,
     runner: Runner,
+    checker: Option<Runner>,
     op: JobOperation,
     pub state: JobState,
     state_tx: JobStateSender,
-    started_at: OffsetDateTime,
     shutdown_rx: ShutdownReceiver,
 }
 
@@ -161,90 +459,172 @@ impl Job {
         id: u64,
         request: JobRequest,
         shutdown_rx: ShutdownReceiver,
-        config: &LanguageConfig,
-    ) -> Result<(Self, JobStateReceiver), String> {
+        cancel_rx: CancelReceiver,
+        config: &RunConfig,
+        state_tx: JobStateSender,
+    ) -> Result<Self, String> {
         let mut state = match request.op {
-            JobOperation::Judging(ref cases) => JobState::new_judging(cases.len()),
+            JobOperation::Judging(ref cases) | JobOperation::Interactive(ref cases)
+                if request.samples_only =>
+            {
+                JobState::new_samples(cases.len())
+            }
+            JobOperation::Judging(ref cases) | JobOperation::Interactive(ref cases) => {
+                JobState::new_judging(cases.len(), request.run_all_cases)
+            }
             JobOperation::Testing(_) => JobState::new_testing(),
         };
 
-        let res = Runner::new(
-            id,
-            &config.compile_cmd,
-            &config.run_cmd,
-            &config.file_name,
-            &request.program,
-            request.cpu_time,
-        )
-        .await;
-        match res {
-            Ok(runner) => {
+        match Self::make_runners(id, &request, config, cancel_rx).await {
+            Ok((runner, checker)) => {
                 info!("Job {} Runner created", id);
-                let (state_tx, state_rx) = tokio::sync::watch::channel(state.clone());
-                Ok((
-                    Self {
-                        id,
-                        runner,
-                        state,
-                        state_tx,
-                        user_id: request.user_id,
-                        op: request.op,
-                        started_at: OffsetDateTime::now_utc(),
-                        shutdown_rx,
-                    },
-                    state_rx,
-                ))
+                state_tx.send_replace(state.clone());
+                Ok(Self {
+                    id,
+                    runner,
+                    checker,
+                    state,
+                    state_tx,
+                    user_id: request.user_id,
+                    op: request.op,
+                    shutdown_rx,
+                })
             }
             Err(e) => {
-                state.complete_case(0, e.clone().into());
+                state.start_first();
+                state.stop_at(0, e.clone().into());
+                state_tx.send_replace(state);
                 Err(format!("Job {} Couldn't create runner: {:?}", id, e))
             }
         }
     }
 
-    pub async fn run(mut self) -> (JobState, OffsetDateTime) {
+    async fn make_runner(
+        id: u64,
+        request: &JobRequest,
+        config: &RunConfig,
+        language: &str,
+        program: &str,
+        cancel_rx: CancelReceiver,
+    ) -> Result<Runner, CaseError> {
+        let language_config = config
+            .languages
+            .get(language)
+            .ok_or_else(|| CaseError::Judge(format!("Language {} not found", language)))?;
+        Runner::new(
+            id,
+            language_config,
+            &config.sandbox,
+            program,
+            request.cpu_time,
+            request.memory_limit,
+            cancel_rx,
+        )
+        .await
+    }
+
+    async fn make_runners(
+        id: u64,
+        request: &JobRequest,
+        config: &RunConfig,
+        cancel_rx: CancelReceiver,
+    ) -> Result<(Runner, Option<Runner>), CaseError> {
+        let runner = Self::make_runner(
+            id,
+            request,
+            config,
+            &request.language,
+            &request.program,
+            cancel_rx.clone(),
+        )
+        .await?;
+
+        let checker = match (&request.op, &request.checker) {
+            (JobOperation::Judging(_) | JobOperation::Interactive(_), Some(checker)) => Some(
+                Self::make_runner(
+                    id,
+                    request,
+                    config,
+                    &checker.language,
+                    &checker.program,
+                    cancel_rx,
+                )
+                .await?,
+            ),
+            (JobOperation::Interactive(_), None) => {
+                return Err(CaseError::Judge(
+                    "Interactive problem has no interactor".to_string(),
+                ))
+            }
+            _ => None,
+        };
+
+        Ok((runner, checker))
+    }
+
+    fn finish(self) -> JobResult {
+        JobResult { state: self.state }
+    }
+
+    pub async fn run(mut self) -> JobResult {
         self.state.start_first();
         self.publish_state();
         if let Err(why) = self.runner.compile().await {
             info!("Job {} Compilation Failed", self.id);
-            if matches!(&self.state, JobState::Testing { .. }) {
-                match why {
-                    CaseError::Compilation(e) => {
-                        self.state
-                            .complete_case(0, CaseStatus::Failed(format!("Compile Error: {}", e)));
-                    }
-                    _ => {
-                        self.state.complete_case(0, why.into());
-                    }
-                }
-            } else {
-                self.state.complete_case(0, why.into());
-            }
+            // The compiler's output is only about the user's own code, so it's always shown
+            self.state.stop_at(0, why.into_status(None, true));
             self.publish_state();
-            return (self.state, self.started_at);
+            return self.finish();
+        }
+        if let Some(checker) = self.checker.as_mut() {
+            if let Err(why) = checker.compile().await {
+                let status = if matches!(why, CaseError::Cancelled) {
+                    why.into()
+                } else {
+                    error!("Job {} Checker failed to compile: {:?}", self.id, why);
+                    CaseError::Judge("Checker failed to compile".to_string()).into()
+                };
+                self.state.stop_at(0, status);
+                self.publish_state();
+                return self.finish();
+            }
         }
         info!(
             "Job {} Starting, requested by user {}",
             self.id, self.user_id
         );
         match &self.op {
-            JobOperation::Judging(cases) => {
+            JobOperation::Judging(cases) | JobOperation::Interactive(cases) => {
                 for (i, case) in cases.iter().enumerate() {
                     info!("Job {} Running Case {}", self.id, i + 1);
-                    let status = match self.runner.run_case(case).await {
-                        Ok(_) => CaseStatus::Passed(None),
-                        Err(e) => match &e {
-                            CaseError::Judge(ref why) => {
+                    let (res, usage) = match (&self.op, self.checker.as_ref()) {
+                        (JobOperation::Interactive(_), Some(interactor)) => {
+                            self.runner.run_interactive(case, interactor).await
+                        }
+                        (_, checker) => self.runner.run_case(case, checker).await,
+                    };
+                    if let Err(CaseError::Cancelled) = res {
+                        info!("Job {} Cancelled during case {}", self.id, i + 1);
+                        self.state
+                            .stop_at(i, CaseError::Cancelled.into_status(usage, false));
+                        self.publish_state();
+                        return self.finish();
+                    }
+                    let status = match res {
+                        Ok(_) => {
+                            CaseStatus::Passed(CaseReport::new(Verdict::Accepted, None, usage))
+                        }
+                        Err(e) => {
+                            if let CaseError::Judge(ref why) = e {
                                 error!(
                                     "Job {} Case {} had a judging error: {:?}",
                                     self.id,
                                     i + 1,
                                     why
                                 );
-                                e.into()
                             }
-                            _ => e.into(),
-                        },
+                            e.into_status(usage, false)
+                        }
                     };
                     info!(
                         "Job {} Case {} finished with status {:?}",
@@ -259,24 +639,24 @@ impl Job {
                     }
                     if self.shutdown_rx.has_changed().unwrap_or(false) {
                         info!("Job {} Received Shutdown Signal, Cancelling", self.id);
-                        return (self.state, self.started_at);
+                        return self.finish();
                     }
                 }
             }
             JobOperation::Testing(input) => {
                 info!("Job {} Running Test", self.id);
-                let status = match self.runner.run_cmd(input).await {
-                    Ok(out) => CaseStatus::Passed(Some(out)),
-                    Err(e) => match &e {
-                        CaseError::Judge(ref why) => {
+                let (res, usage) = self.runner.run_cmd(input).await;
+                let status = match res {
+                    Ok(out) => {
+                        CaseStatus::Passed(CaseReport::new(Verdict::Accepted, Some(out), usage))
+                    }
+                    Err(e) => {
+                        if let CaseError::Judge(ref why) = e {
                             error!("Job {} Test had a judging error: {:?}", self.id, why);
-                            e.into()
-                        }
-                        CaseError::Runtime(ref msg) => {
-                            CaseStatus::Failed(format!("Runtime Error: {}", msg))
                         }
-                        _ => e.into(),
-                    },
+                        // Users are testing with their own input, so they can see what went wrong
+                        e.into_status(usage, true)
+                    }
                 };
                 info!(
                     "Job {} Test finished with status {:?}",
@@ -289,7 +669,7 @@ impl Job {
         }
 
         info!("Job {} Finished", self.id);
-        (self.state, self.started_at)
+        self.finish()
     }
 
     pub fn publish_state(&self) {
@@ -2,6 +2,30 @@ use std::collections::HashMap;
 
 use serde::Deserialize;
 
+use super::sandbox::SandboxConfig;
+
+fn default_output_limit() -> usize {
+    1024
+}
+
+fn default_compile_time_limit() -> u64 {
+    10
+}
+
+fn default_compile_memory_limit() -> u64 {
+    1024
+}
+
+fn default_ui_pacing_ms() -> u64 {
+    250
+}
+
+fn default_max_workers() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
 #[derive(Deserialize, Serialize, Clone)]
 #[serde(crate = "rocket::serde")]
 /// Specifies a configuration for a language.
@@ -26,6 +50,15 @@ pub struct LanguageConfig {
     #[serde(skip_serializing)]
     /// Command to run the program. This will be passed the case's input in stdin
     pub run_cmd: String,
+    #[serde(skip_serializing, default = "default_output_limit")]
+    /// Max amount of output (in kilobytes) the program can print before it's killed
+    pub output_limit: usize,
+    #[serde(skip_serializing, default = "default_compile_time_limit")]
+    /// Max CPU time (in seconds) the compiler can take, it's killed after twice this in wall time
+    pub compile_time_limit: u64,
+    #[serde(skip_serializing, default = "default_compile_memory_limit")]
+    /// Max memory (in megabytes) the compiler can use
+    pub compile_memory_limit: u64,
 }
 
 #[derive(Deserialize, Clone)]
@@ -35,6 +68,16 @@ pub struct RunConfig {
     pub languages: HashMap<String, LanguageConfig>,
     /// Default language to use
     pub default_language: String,
+    #[serde(default)]
+    /// How to isolate programs while compiling and running them
+    pub sandbox: SandboxConfig,
+    #[serde(default = "default_max_workers")]
+    /// How many jobs can be running at once, others will wait in a queue
+    pub max_workers: usize,
+    #[serde(default = "default_ui_pacing_ms")]
+    /// Minimum milliseconds between job updates sent to a user so they can see cases finish one by one,
+    /// this only affects what's shown and doesn't slow down judging
+    pub ui_pacing_ms: u64,
 }
 
 impl RunConfig {
@@ -1,46 +1,145 @@
 use std::collections::HashMap;
 use std::sync::Arc;
 
-use log::{error, info};
+use log::{error, info, warn};
+use rocket::time::OffsetDateTime;
 use rocket_db_pools::Pool;
 use tokio::sync::Mutex;
 
-use crate::db::DbPool;
-use crate::problems::JudgeRun;
+use crate::db::{DbPool, DbPoolConnection};
+use crate::problems::{JudgeRun, JudgeRunCase, Problem, TestCase};
 
-use super::job::{Job, JobRequest};
+use super::job::{Job, JobOperation, JobRequest, JobResult};
+use super::runner::CaseError;
 
 use super::languages::RunConfig;
+use super::queue::{JobQueue, PersistedJob, QueuedJob};
 use super::{JobState, JobStateReceiver};
 
 type UserId = i64;
 
-type RunHandle = Arc<Mutex<Option<(i64, JobStateReceiver)>>>;
+pub type RunHandle = Arc<Mutex<Option<(i64, JobStateReceiver, CancelSender)>>>;
+
+/// Sending `true` cancels a job, killing whatever it's currently running
+pub type CancelSender = tokio::sync::watch::Sender<bool>;
+pub type CancelReceiver = tokio::sync::watch::Receiver<bool>;
 
 pub type JobStartedMessage = (UserId, i64, JobStateReceiver);
 pub type JobStartedReceiver = tokio::sync::broadcast::Receiver<JobStartedMessage>;
 pub type JobStartedSender = tokio::sync::broadcast::Sender<JobStartedMessage>;
 
+/// Sent when a judge run is saved, used to update scoreboards
+pub type RunCompletedMessage = (UserId, i64);
+pub type RunCompletedReceiver = tokio::sync::broadcast::Receiver<RunCompletedMessage>;
+pub type RunCompletedSender = tokio::sync::broadcast::Sender<RunCompletedMessage>;
+
 pub type ShutdownReceiver = tokio::sync::watch::Receiver<bool>;
 
+/// What's needed to save the result of a job once it's done, taken from its request
+struct RunRecord {
+    user_id: i64,
+    problem_id: i64,
+    program: String,
+    language: String,
+    cases: Vec<TestCase>,
+    rejudge_run_id: Option<i64>,
+    queued_at: OffsetDateTime,
+    /// Only judging runs are saved, tests and samples aren't
+    save: bool,
+}
+
+impl RunRecord {
+    fn new(request: &JobRequest, queued_at: OffsetDateTime) -> Self {
+        let cases = match &request.op {
+            JobOperation::Judging(cases) | JobOperation::Interactive(cases) => cases.clone(),
+            JobOperation::Testing(_) => vec![],
+        };
+        Self {
+            user_id: request.user_id,
+            problem_id: request.problem_id,
+            program: request.program.clone(),
+            language: request.language.clone(),
+            save: !request.samples_only && !matches!(request.op, JobOperation::Testing(_)),
+            rejudge_run_id: request.rejudge_run_id,
+            queued_at,
+            cases,
+        }
+    }
+
+    async fn save(
+        self,
+        conn: &mut DbPoolConnection,
+        result: &JobResult,
+        run_completed_tx: &RunCompletedSender,
+    ) {
+        if !self.save || !matches!(result.state, JobState::Judging { .. }) {
+            return;
+        }
+        let judge_run = JudgeRun::from_job_result(
+            self.problem_id,
+            self.user_id,
+            self.program,
+            self.language,
+            result,
+            &self.cases,
+            self.queued_at,
+        );
+        let res = match self.rejudge_run_id {
+            Some(run_id) => judge_run.update_verdict(conn, run_id).await,
+            None => judge_run.write_to_db(conn).await,
+        };
+        match res {
+            Ok(run) => {
+                info!("Judge run written to db");
+                let run_cases = JudgeRunCase::from_job_result(result);
+                if let Err(e) = JudgeRunCase::write_all(conn, run.id, run_cases).await {
+                    error!("Couldn't write case results for run {}: {:?}", run.id, e);
+                }
+                // Nobody listening is fine
+                run_completed_tx.send((self.user_id, self.problem_id)).ok();
+            }
+            Err(e) => {
+                error!("Couldn't write judge run to db: {:?}", e);
+            }
+        }
+    }
+}
+
 pub struct RunManager {
     config: RunConfig,
     id_counter: u64,
     jobs: HashMap<UserId, RunHandle>,
+    queue: Arc<JobQueue>,
     db_pool: DbPool,
     job_started_channel: (JobStartedSender, JobStartedReceiver),
+    run_completed_tx: RunCompletedSender,
     shutdown_rx: ShutdownReceiver,
 }
 
 impl RunManager {
     pub fn new(config: RunConfig, pool: DbPool, shutdown_rx: ShutdownReceiver) -> Self {
         let (tx, rx) = tokio::sync::broadcast::channel(10);
+        let (run_completed_tx, _) = tokio::sync::broadcast::channel(10);
+        let queue = Arc::new(JobQueue::default());
+
+        for _ in 0..config.max_workers {
+            tokio::spawn(Self::worker(
+                queue.clone(),
+                config.clone(),
+                pool.clone(),
+                run_completed_tx.clone(),
+                shutdown_rx.clone(),
+            ));
+        }
+
         Self {
             config,
             id_counter: 1,
             jobs: HashMap::with_capacity(10),
+            queue,
             db_pool: pool,
             job_started_channel: (tx, rx),
+            run_completed_tx,
             shutdown_rx,
         }
     }
@@ -49,78 +148,309 @@ impl RunManager {
         self.job_started_channel.0.subscribe()
     }
 
+    pub fn subscribe_run_completed(&self) -> RunCompletedReceiver {
+        self.run_completed_tx.subscribe()
+    }
+
+    /// Tell scoreboards a run changed without judging anything, used when revealing a frozen scoreboard
+    pub fn notify_run_completed(&self, msg: RunCompletedMessage) {
+        self.run_completed_tx.send(msg).ok();
+    }
+
     pub fn subscribe_shutdown(&self) -> ShutdownReceiver {
         self.shutdown_rx.clone()
     }
 
-    async fn start_job(&mut self, request: JobRequest) -> Result<(), String> {
+    async fn worker(
+        queue: Arc<JobQueue>,
+        config: RunConfig,
+        pool: DbPool,
+        run_completed_tx: RunCompletedSender,
+        mut shutdown_rx: ShutdownReceiver,
+    ) {
+        loop {
+            let queued = tokio::select! {
+                job = queue.pop() => job,
+                _ = shutdown_rx.changed() => break,
+            };
+            Self::run_job(queued, &config, &pool, &run_completed_tx, &shutdown_rx).await;
+        }
+    }
+
+    async fn run_job(
+        queued: QueuedJob,
+        config: &RunConfig,
+        pool: &DbPool,
+        run_completed_tx: &RunCompletedSender,
+        shutdown_rx: &ShutdownReceiver,
+    ) {
+        let QueuedJob {
+            id,
+            row_id,
+            request,
+            queued_at,
+            state_tx,
+            handle,
+            cancel_rx,
+        } = queued;
+
+        let record = RunRecord::new(&request, queued_at);
+
+        let res = Job::new(
+            id,
+            request,
+            shutdown_rx.clone(),
+            cancel_rx,
+            config,
+            state_tx,
+        )
+        .await;
+
+        let res = match res {
+            Ok(job) => Some(job.run().await),
+            Err(e) => {
+                error!("Couldn't create job: {:?}", e);
+                None
+            }
+        };
+
+        handle.lock().await.take();
+        drop(handle);
+
+        if *shutdown_rx.borrow() {
+            info!("Job {} Interrupted by shutdown, leaving it queued", id);
+            return;
+        }
+
+        let mut conn = match pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Couldn't get db connection: {:?}", e);
+                return;
+            }
+        };
+
+        if let Some(result) = res {
+            record.save(&mut conn, &result, run_completed_tx).await;
+        }
+
+        if let Some(row_id) = row_id {
+            if let Err(e) = PersistedJob::delete(&mut conn, row_id).await {
+                error!("Couldn't remove job {} from the queue: {:?}", id, e);
+            }
+        }
+    }
+
+    /// Add a job to the queue, `persisted` is the row ID and queue time of a job that's already persisted
+    async fn enqueue(
+        &mut self,
+        request: JobRequest,
+        persisted: Option<(i64, OffsetDateTime)>,
+    ) -> Result<(), String> {
+        if !self.config.languages.contains_key(&request.language) {
+            return Err(format!(
+                "Language {} not supported by runner",
+                request.language
+            ));
+        }
+
+        if let Some(checker) = &request.checker {
+            if !self.config.languages.contains_key(&checker.language) {
+                return Err(format!(
+                    "Checker language {} not supported by runner",
+                    checker.language
+                ));
+            }
+        }
+
         let id = self.id_counter;
         self.id_counter += 1;
 
         let user_id = request.user_id;
         let problem_id = request.problem_id;
 
-        let language_config = self
-            .config
-            .languages
-            .get(&request.language)
-            .ok_or_else(|| format!("Language {} not supported by runner", request.language))?;
+        let rejudge = request.rejudge_run_id.is_some();
 
-        let (job, state_rx) = Job::new(id, request, self.shutdown_rx.clone(), language_config)
-            .await
-            .map_err(|e| {
-                error!("Couldn't create job: {:?}", e);
-                "Judge Error".to_string()
-            })?;
-
-        let handle = Arc::new(Mutex::new(Some((problem_id, state_rx.clone()))));
-
-        self.jobs.insert(user_id, handle.clone());
-
-        let pool = self.db_pool.clone();
-
-        tokio::spawn(async move {
-            let (state, ran_at) = job.run().await;
-            handle.lock().await.take();
-            drop(handle);
-            if matches!(state, JobState::Judging { .. }) {
-                let judge_run = JudgeRun::from_job_state(problem_id, user_id, state, ran_at);
-                match pool.get().await {
-                    Ok(mut conn) => match judge_run.write_to_db(&mut conn).await {
-                        Ok(_) => {
-                            info!("Judge run written to db");
-                        }
-                        Err(e) => {
-                            error!("Couldn't write judge run to db: {:?}", e);
-                        }
-                    },
+        let (row_id, queued_at) = match persisted {
+            Some((row_id, queued_at)) => (Some(row_id), queued_at),
+            None => {
+                let queued_at = OffsetDateTime::now_utc();
+                let row_id = match self.db_pool.get().await {
+                    Ok(mut conn) => PersistedJob::save(&mut conn, &request, queued_at)
+                        .await
+                        .map_err(|e| error!("Couldn't persist job {}: {:?}", id, e))
+                        .ok()
+                        .flatten(),
                     Err(e) => {
                         error!("Couldn't get db connection: {:?}", e);
+                        None
                     }
-                }
+                };
+                (row_id, queued_at)
             }
-        });
+        };
+
+        let (state_tx, state_rx) = tokio::sync::watch::channel(JobState::Queued { position: 0 });
+        let (cancel_tx, cancel_rx) = tokio::sync::watch::channel(false);
+
+        let handle = Arc::new(Mutex::new(Some((problem_id, state_rx.clone(), cancel_tx))));
+
+        // Rejudges happen in the background, so they don't take up the user's slot or show up for them
+        if !rejudge {
+            self.jobs.insert(user_id, handle.clone());
+        }
+
+        self.queue
+            .push(QueuedJob {
+                id,
+                row_id,
+                request,
+                queued_at,
+                state_tx,
+                handle,
+                cancel_rx,
+            })
+            .await;
 
-        self.job_started_channel
-            .0
-            .send((user_id, problem_id, state_rx))
-            .ok();
+        if !rejudge {
+            self.job_started_channel
+                .0
+                .send((user_id, problem_id, state_rx))
+                .ok();
+        }
 
         Ok(())
     }
 
+    /// Re-queue any judging jobs that were saved before the server last shut down
+    pub async fn restore_queue(&mut self) {
+        let mut conn = match self.db_pool.get().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                error!("Couldn't get db connection: {:?}", e);
+                return;
+            }
+        };
+
+        let persisted = PersistedJob::list(&mut conn).await.unwrap_or_else(|e| {
+            error!("Couldn't load queued jobs: {:?}", e);
+            vec![]
+        });
+
+        for job in persisted {
+            let problem = Problem::get(&mut conn, job.problem_id).await;
+            let cases = TestCase::get_for_problem(&mut conn, job.problem_id)
+                .await
+                .unwrap_or_default();
+            let res = match problem {
+                Some(problem) if !cases.is_empty() => {
+                    let mut request =
+                        JobRequest::judge(&problem, cases, job.user_id, job.program, job.language);
+                    request.rejudge_run_id = job.rejudge_run_id;
+                    self.enqueue(request, Some((job.id, job.queued_at))).await
+                }
+                _ => Err("Problem no longer exists".to_string()),
+            };
+            if let Err(why) = res {
+                warn!("Couldn't restore queued job {}: {}", job.id, why);
+                PersistedJob::delete(&mut conn, job.id).await.ok();
+            }
+        }
+    }
+
     pub async fn get_handle(&self, user_id: UserId, problem_id: i64) -> Option<JobStateReceiver> {
         if let Some(handle) = self.jobs.get(&user_id) {
             let handle = handle.lock().await;
             handle
                 .as_ref()
-                .filter(|(id, _)| *id == problem_id)
-                .map(|(_, rx)| rx.clone())
+                .filter(|(id, _, _)| *id == problem_id)
+                .map(|(_, rx, _)| rx.clone())
         } else {
             None
         }
     }
 
+    /// Judge a saved run again with the problem's current test cases, replacing its verdict
+    pub async fn request_rejudge(
+        &mut self,
+        problem: &Problem,
+        cases: Vec<TestCase>,
+        run: &JudgeRun,
+    ) -> Result<(), String> {
+        let mut request = JobRequest::judge(
+            problem,
+            cases,
+            run.user_id,
+            run.program.clone(),
+            run.language.clone(),
+        );
+        request.rejudge_run_id = Some(run.id);
+        self.enqueue(request, None).await
+    }
+
+    /// Cancel the user's job for a problem. A running job has its process killed and the case it was on
+    /// marked as cancelled, a job that hasn't started yet is taken out of the queue.
+    /// Either way the cancellation is saved as a run and the user can start another job.
+    pub async fn cancel_job(&mut self, user_id: UserId, problem_id: i64) -> Result<(), String> {
+        let handle = self
+            .jobs
+            .get(&user_id)
+            .cloned()
+            .ok_or_else(|| "No job to cancel".to_string())?;
+        let mut handle_lock = handle.lock().await;
+        match handle_lock.as_ref() {
+            Some((id, _, cancel_tx)) if *id == problem_id => {
+                cancel_tx.send_replace(true);
+            }
+            _ => return Err("No job to cancel".to_string()),
+        }
+
+        // If a worker already took the job it will stop on its own now that it's been cancelled
+        let Some(queued) = self.queue.remove(&handle).await else {
+            return Ok(());
+        };
+        handle_lock.take();
+        drop(handle_lock);
+
+        info!("Job {} Cancelled before it started", queued.id);
+
+        let mut state = match &queued.request.op {
+            JobOperation::Judging(cases) | JobOperation::Interactive(cases)
+                if queued.request.samples_only =>
+            {
+                JobState::new_samples(cases.len())
+            }
+            JobOperation::Judging(cases) | JobOperation::Interactive(cases) => {
+                JobState::new_judging(cases.len(), queued.request.run_all_cases)
+            }
+            JobOperation::Testing(_) => JobState::new_testing(),
+        };
+        if state.len() > 0 {
+            state.stop_at(0, CaseError::Cancelled.into());
+        }
+        queued.state_tx.send_replace(state.clone());
+
+        let result = JobResult { state };
+
+        match self.db_pool.get().await {
+            Ok(mut conn) => {
+                RunRecord::new(&queued.request, queued.queued_at)
+                    .save(&mut conn, &result, &self.run_completed_tx)
+                    .await;
+                if let Some(row_id) = queued.row_id {
+                    if let Err(e) = PersistedJob::delete(&mut conn, row_id).await {
+                        error!("Couldn't remove job {} from the queue: {:?}", queued.id, e);
+                    }
+                }
+            }
+            Err(e) => {
+                error!("Couldn't get db connection: {:?}", e);
+            }
+        }
+
+        Ok(())
+    }
+
     pub async fn request_job(&mut self, request: JobRequest) -> Result<(), String> {
         if let Some(handle) = self.jobs.get(&request.user_id) {
             let handle = handle.lock().await;
@@ -128,10 +458,10 @@ impl RunManager {
                 Err("User already has a job running".to_string())
             } else {
                 drop(handle);
-                self.start_job(request).await
+                self.enqueue(request, None).await
             }
         } else {
-            self.start_job(request).await
+            self.enqueue(request, None).await
         }
     }
 }
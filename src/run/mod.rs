@@ -9,10 +9,13 @@ use crate::db::Database;
 
 use self::manager::RunManager;
 
+mod cgroup;
 mod job;
 mod languages;
 mod manager;
+mod queue;
 mod runner;
+mod sandbox;
 mod ws;
 
 pub type JobStateMessage = job::JobState;
@@ -22,7 +25,7 @@ pub type JobStateReceiver = tokio::sync::watch::Receiver<JobStateMessage>;
 
 pub type ManagerHandle = Arc<Mutex<RunManager>>;
 
-pub use job::JobState;
+pub use job::{JobResult, JobState, Verdict};
 pub use languages::RunConfig;
 
 pub struct CodeInfo {
@@ -65,8 +68,13 @@ pub fn stage() -> AdHoc {
                         return Err(rocket);
                     }
                 };
+                if config.max_workers == 0 {
+                    error!("'run.max_workers' must be at least 1 or no jobs would ever run");
+                    return Err(rocket);
+                }
                 let code_info = serde_json::to_string(&config.languages).unwrap();
-                let manager = manager::RunManager::new(config.clone(), pool, rx);
+                let mut manager = manager::RunManager::new(config.clone(), pool, rx);
+                manager.restore_queue().await;
                 Ok(rocket
                     .attach(shutdown_fairing)
                     .manage::<CodeInfo>(CodeInfo {
@@ -0,0 +1,157 @@
+use std::{collections::VecDeque, sync::Arc};
+
+use rocket::time::OffsetDateTime;
+use tokio::sync::{Mutex, Notify};
+
+use crate::db::DbPoolConnection;
+
+use super::{
+    job::{JobOperation, JobRequest},
+    manager::{CancelReceiver, RunHandle},
+    JobState, JobStateSender,
+};
+
+/// A job waiting for a free worker
+pub struct QueuedJob {
+    pub id: u64,
+    /// ID of the row persisting this job, only judging jobs are persisted
+    pub row_id: Option<i64>,
+    pub request: JobRequest,
+    /// When the job was submitted, this is what a judged run is saved with so time spent waiting doesn't count
+    pub queued_at: OffsetDateTime,
+    pub state_tx: JobStateSender,
+    pub handle: RunHandle,
+    pub cancel_rx: CancelReceiver,
+}
+
+/// Jobs waiting for a worker, split by priority
+#[derive(Default)]
+struct Queues {
+    /// Jobs users are waiting on
+    live: VecDeque<QueuedJob>,
+    /// Rejudges, these only run when no live jobs are waiting so a big rejudge can't hold up contestants
+    rejudges: VecDeque<QueuedJob>,
+}
+
+impl Queues {
+    fn pop_front(&mut self) -> Option<QueuedJob> {
+        self.live.pop_front().or_else(|| self.rejudges.pop_front())
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &QueuedJob> {
+        self.live.iter().chain(self.rejudges.iter())
+    }
+}
+
+/// FIFO queue of jobs waiting for a worker, with rejudges waiting behind every live job.
+/// Users can only have one job queued or running at a time, so FIFO order is also fair across users.
+#[derive(Default)]
+pub struct JobQueue {
+    jobs: Mutex<Queues>,
+    notify: Notify,
+}
+
+impl JobQueue {
+    pub async fn push(&self, job: QueuedJob) {
+        let mut jobs = self.jobs.lock().await;
+        if job.request.rejudge_run_id.is_some() {
+            jobs.rejudges.push_back(job);
+        } else {
+            jobs.live.push_back(job);
+        }
+        Self::publish_positions(&jobs);
+        drop(jobs);
+        self.notify.notify_one();
+    }
+
+    /// Wait until a job is available and take it off the front of the queue
+    pub async fn pop(&self) -> QueuedJob {
+        loop {
+            let mut jobs = self.jobs.lock().await;
+            if let Some(job) = jobs.pop_front() {
+                Self::publish_positions(&jobs);
+                return job;
+            }
+            drop(jobs);
+            self.notify.notified().await;
+        }
+    }
+
+    /// Take a job out of the queue before it starts, returns `None` if a worker already took it
+    pub async fn remove(&self, handle: &RunHandle) -> Option<QueuedJob> {
+        let mut guard = self.jobs.lock().await;
+        let jobs = &mut *guard;
+        let job = [&mut jobs.live, &mut jobs.rejudges]
+            .into_iter()
+            .find_map(|queue| {
+                let idx = queue.iter().position(|j| Arc::ptr_eq(&j.handle, handle))?;
+                queue.remove(idx)
+            })?;
+        Self::publish_positions(jobs);
+        Some(job)
+    }
+
+    fn publish_positions(jobs: &Queues) {
+        for (i, job) in jobs.iter().enumerate() {
+            job.state_tx
+                .send_replace(JobState::Queued { position: i + 1 });
+        }
+    }
+}
+
+/// A judging job saved to the database so it survives a restart
+pub struct PersistedJob {
+    pub id: i64,
+    pub user_id: i64,
+    pub problem_id: i64,
+    pub language: String,
+    pub program: String,
+    pub rejudge_run_id: Option<i64>,
+    pub queued_at: OffsetDateTime,
+}
+
+impl PersistedJob {
+    pub async fn save(
+        db: &mut DbPoolConnection,
+        request: &JobRequest,
+        queued_at: OffsetDateTime,
+    ) -> Result<Option<i64>, sqlx::Error> {
+        if !request.samples_only
+            && matches!(
+                request.op,
+                JobOperation::Judging(_) | JobOperation::Interactive(_)
+            )
+        {
+            sqlx::query!(
+                "INSERT INTO queued_job (user_id, problem_id, language, program, rejudge_run_id, queued_at) VALUES (?, ?, ?, ?, ?, ?) RETURNING id",
+                request.user_id,
+                request.problem_id,
+                request.language,
+                request.program,
+                request.rejudge_run_id,
+                queued_at
+            )
+            .fetch_one(&mut **db)
+            .await
+            .map(|r| Some(r.id))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub async fn list(db: &mut DbPoolConnection) -> Result<Vec<Self>, sqlx::Error> {
+        sqlx::query_as!(
+            PersistedJob,
+            r#"SELECT id, user_id, problem_id, language, program, rejudge_run_id, queued_at AS "queued_at: OffsetDateTime" FROM queued_job ORDER BY id"#
+        )
+        .fetch_all(&mut **db)
+        .await
+    }
+
+    pub async fn delete(db: &mut DbPoolConnection, id: i64) -> Result<(), sqlx::Error> {
+        sqlx::query!("DELETE FROM queued_job WHERE id = ?", id)
+            .execute(&mut **db)
+            .await
+            .map(|_| ())
+    }
+}
@@ -1,52 +1,243 @@
-use std::{path::PathBuf, process::Stdio};
+use std::{
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::PathBuf,
+    process::{ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
 
 use log::error;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
 
 use crate::problems::TestCase;
 
-use super::job::CaseStatus;
+use super::{
+    cgroup::Cgroup,
+    job::{CaseReport, CaseStatus, ResourceUsage, Verdict},
+    languages::LanguageConfig,
+    manager::CancelReceiver,
+    sandbox::SandboxConfig,
+};
 
 #[derive(Debug, Clone)]
 pub enum CaseError {
-    Logic,
-    //TimeLimitExceeded,
-    Runtime(String),
+    Logic(Option<String>),
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+    OutputLimitExceeded,
+    Runtime {
+        message: String,
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
     Compilation(String),
+    /// The compiler went over its time limit
+    CompilationTimedOut,
     Judge(String),
+    /// The user cancelled the job while this was running
+    Cancelled,
+}
+
+impl CaseError {
+    pub fn verdict(&self) -> Verdict {
+        match self {
+            CaseError::Logic(_) => Verdict::WrongAnswer,
+            CaseError::TimeLimitExceeded => Verdict::TimeLimitExceeded,
+            CaseError::MemoryLimitExceeded => Verdict::MemoryLimitExceeded,
+            CaseError::OutputLimitExceeded => Verdict::OutputLimitExceeded,
+            CaseError::Runtime { .. } => Verdict::RuntimeError,
+            CaseError::Compilation(_) => Verdict::CompileError,
+            CaseError::CompilationTimedOut => Verdict::CompileTimeLimitExceeded,
+            CaseError::Judge(_) => Verdict::JudgeError,
+            CaseError::Cancelled => Verdict::Cancelled,
+        }
+    }
+
+    /// Make the status of a case that failed with this error. Unless `detailed` is set, stderr and compiler output
+    /// are left out as they could reveal the test data, this is only set when users test with their own input.
+    pub fn into_status(self, usage: Option<ResourceUsage>, detailed: bool) -> CaseStatus {
+        let mut report = CaseReport::new(self.verdict(), None, usage);
+        match self {
+            CaseError::Logic(message) => {
+                report.message = message;
+            }
+            CaseError::Runtime {
+                message,
+                code,
+                signal,
+            } => {
+                report.exit_code = code;
+                report.signal = signal;
+                report.message = detailed.then_some(message);
+            }
+            CaseError::Compilation(message) => {
+                report.message = detailed.then_some(message);
+            }
+            _ => {}
+        }
+        CaseStatus::Failed(report)
+    }
 }
 
 impl From<CaseError> for CaseStatus {
     fn from(val: CaseError) -> Self {
-        CaseStatus::Failed(match val {
-            CaseError::Logic => "Logic error".to_string(),
-            //CaseError::TimeLimitExceeded => "Time limit exceeded".to_string(),
-            CaseError::Runtime(_) => "Runtime error".to_string(),
-            CaseError::Compilation(_) => "Compile error".to_string(),
-            CaseError::Judge(_) => "Judge error".to_string(),
-        })
+        val.into_status(None, false)
     }
 }
 
 pub type CaseResult<T = ()> = Result<T, CaseError>;
 
+/// The result of running a program along with the resources it used, which are missing if it didn't get to exit
+pub type Measured<T> = (CaseResult<T>, Option<ResourceUsage>);
+
+/// How many times the CPU time limit a program is allowed to take in wall time before it's killed,
+/// this catches programs that are sleeping or blocked instead of using the CPU
+const WALL_TIME_MULTIPLIER: u64 = 2;
+
+/// How often to check the memory usage of a running program's cgroup, this is only used for reporting
+const MEMORY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long to keep reading a program's output after it exits. Anything it started that's still
+/// holding its stdout or stderr open after this is given up on, so it can't stall the worker.
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Limits a process is run with
+#[derive(Clone, Copy)]
+struct Limits {
+    /// Max CPU time in seconds, the process is killed after [WALL_TIME_MULTIPLIER] times this in wall time
+    cpu_time: u64,
+    /// Max memory in bytes
+    memory: u64,
+    /// Max output in bytes
+    output: usize,
+}
+
+/// A process started by [Runner::spawn]
+struct Process {
+    child: std::process::Child,
+    /// The cgroup the process is in, if the sandbox has one
+    cgroup: Option<Cgroup>,
+}
+
+impl Process {
+    fn pid(&self) -> i32 {
+        self.child.id() as i32
+    }
+
+    /// Kill the process and everything it started
+    fn kill(&self) {
+        kill_group(self.pid());
+        if let Some(cgroup) = &self.cgroup {
+            cgroup.kill();
+        }
+    }
+}
+
+/// Output of a process that finished without hitting any limits
+struct ProcessOutput {
+    status: ExitStatus,
+    stdout: String,
+    stderr: String,
+}
+
+/// Information about a process after it has been waited on
+struct ExitInfo {
+    status: ExitStatus,
+    cpu_time: Duration,
+    /// Peak resident memory of the process in bytes
+    max_memory: u64,
+}
+
+fn timeval_to_duration(time: libc::timeval) -> Duration {
+    Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+}
+
+/// Block until the process with the given PID exits, reaping it and collecting its resource usage.
+/// This has to be used instead of [std::process::Child::wait] as that doesn't report resource usage.
+fn wait_for_exit(pid: i32) -> std::io::Result<ExitInfo> {
+    let mut status = 0;
+    // SAFETY: rusage is a plain C struct, all zeroes is a valid value
+    let mut usage = unsafe { std::mem::zeroed::<libc::rusage>() };
+    loop {
+        // SAFETY: both pointers are valid for the duration of the call
+        let res = unsafe { libc::wait4(pid, &mut status, 0, &mut usage) };
+        if res != -1 {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != std::io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+    Ok(ExitInfo {
+        status: ExitStatus::from_raw(status),
+        cpu_time: timeval_to_duration(usage.ru_utime) + timeval_to_duration(usage.ru_stime),
+        // ru_maxrss is in kilobytes on Linux
+        max_memory: usage.ru_maxrss as u64 * 1024,
+    })
+}
+
+/// Kill every process in the given process group, this ensures anything the program forked is also cleaned up
+fn kill_group(pgid: i32) {
+    // SAFETY: killpg has no memory safety requirements, failure (the group is already gone) is fine
+    unsafe {
+        libc::killpg(pgid, libc::SIGKILL);
+    }
+}
+
+/// Resolves once the job is cancelled, if the job can't be cancelled anymore this never resolves
+async fn cancelled(mut cancel_rx: CancelReceiver) {
+    if cancel_rx.wait_for(|c| *c).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Read a stream until it ends or more than `limit` bytes have been read.
+/// Returns what was read (at most `limit` bytes) and whether the limit was exceeded.
+async fn read_limited(
+    stream: &mut (impl AsyncRead + Unpin),
+    limit: usize,
+) -> std::io::Result<(String, bool)> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 8192];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            break Ok((String::from_utf8_lossy(&buf).to_string(), false));
+        } else if buf.len() + read > limit {
+            buf.extend_from_slice(&chunk[..limit - buf.len()]);
+            break Ok((String::from_utf8_lossy(&buf).to_string(), true));
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    }
+}
+
 pub struct Runner {
     run_cmd: String,
     compile_cmd: String,
     file_name: String,
     temp_path: PathBuf,
-    #[allow(dead_code)]
+    sandbox: SandboxConfig,
     max_cpu_time: i64,
+    /// Max memory in megabytes
+    max_memory: i64,
+    /// Max output in bytes
+    max_output: usize,
+    /// Max CPU time of the compiler in seconds
+    compile_time_limit: u64,
+    /// Max memory of the compiler in megabytes
+    compile_memory_limit: u64,
+    cancel_rx: CancelReceiver,
 }
 
 impl Runner {
     pub async fn new(
         id: u64,
-        compile_cmd: &str,
-        run_cmd: &str,
-        file_name: &str,
+        language: &LanguageConfig,
+        sandbox: &SandboxConfig,
         program: &str,
         max_cpu_time: i64,
+        max_memory: i64,
+        cancel_rx: CancelReceiver,
     ) -> CaseResult<Self> {
         let now_nanos = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -61,106 +252,575 @@ impl Runner {
             .await
             .map_err(|e| CaseError::Judge(format!("Couldn't create temp dir: {e:?}")))?;
 
-        tokio::fs::write(temp_path.join(file_name), program.as_bytes())
+        let program_path = temp_path.join(&language.file_name);
+
+        tokio::fs::write(&program_path, program.as_bytes())
             .await
             .map_err(|e| CaseError::Judge(format!("Couldn't write to program file: {e:?}")))?;
 
+        if let Some((uid, gid)) = sandbox.owner() {
+            std::os::unix::fs::chown(&temp_path, uid, gid)
+                .and_then(|_| std::os::unix::fs::chown(&program_path, uid, gid))
+                .map_err(|e| CaseError::Judge(format!("Couldn't set temp dir owner: {e:?}")))?;
+        }
+
         Ok(Self {
-            run_cmd: run_cmd.to_string(),
-            compile_cmd: compile_cmd.to_string(),
-            file_name: file_name.to_string(),
+            run_cmd: language.run_cmd.clone(),
+            compile_cmd: language.compile_cmd.clone(),
+            file_name: language.file_name.clone(),
             temp_path,
+            sandbox: sandbox.clone(),
             max_cpu_time,
+            max_memory,
+            max_output: language.output_limit * 1024,
+            compile_time_limit: language.compile_time_limit,
+            compile_memory_limit: language.compile_memory_limit,
+            cancel_rx,
         })
     }
 
+    /// Limits for running the program
+    fn run_limits(&self) -> Limits {
+        Limits {
+            cpu_time: self.max_cpu_time as u64,
+            memory: self.max_memory as u64 * 1024 * 1024,
+            output: self.max_output,
+        }
+    }
+
+    /// Limits for compiling the program
+    fn compile_limits(&self) -> Limits {
+        Limits {
+            cpu_time: self.compile_time_limit,
+            memory: self.compile_memory_limit * 1024 * 1024,
+            output: self.max_output,
+        }
+    }
+
     pub async fn compile(&mut self) -> Result<(), CaseError> {
         if self.compile_cmd.is_empty() {
+            return Ok(());
+        }
+        let limits = self.compile_limits();
+        let (res, _) = match self.spawn(&self.compile_cmd, Stdio::null(), Stdio::piped(), limits) {
+            Ok(process) => self.supervise(process, "", limits).await,
+            Err(e) => (Err(e), None),
+        };
+        let output = match res {
+            Ok(output) => output,
+            Err(CaseError::TimeLimitExceeded) => return Err(CaseError::CompilationTimedOut),
+            Err(CaseError::MemoryLimitExceeded) => {
+                return Err(CaseError::Compilation(format!(
+                    "The compiler used more than {} MB of memory",
+                    self.compile_memory_limit
+                )))
+            }
+            Err(CaseError::OutputLimitExceeded) => {
+                return Err(CaseError::Compilation(
+                    "The compiler printed too much output".to_string(),
+                ))
+            }
+            Err(e) => return Err(e),
+        };
+        if output.status.success() {
             Ok(())
         } else {
-            let mut cmd = tokio::process::Command::new("bash");
-            cmd.arg("-c")
-                .arg(&self.compile_cmd)
-                .current_dir(&self.temp_path)
-                //.stdin(Stdio::null())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped());
-            let output = cmd
-                .output()
-                .await
-                .map_err(|e| CaseError::Judge(format!("Couldn't run compile command: {e:?}")))?;
-            if !output.status.success() {
-                let std_err = String::from_utf8_lossy(&output.stderr).to_string();
-                Err(CaseError::Compilation(std_err))
+            // Some compilers report errors on stdout instead
+            let raw = if output.stderr.is_empty() {
+                &output.stdout
             } else {
-                Ok(())
-            }
+                &output.stderr
+            };
+            let std_err = self.sanitize(raw);
+            Err(CaseError::Compilation(
+                std_err.chars().take(self.max_output).collect(),
+            ))
         }
     }
 
-    pub async fn run_cmd(&self, input: &str) -> CaseResult<String> {
-        let mut cmd = tokio::process::Command::new("bash");
+    /// Start `script` in this runner's directory with the given limits applied, stderr is always piped
+    fn spawn(
+        &self,
+        script: &str,
+        stdin: Stdio,
+        stdout: Stdio,
+        limits: Limits,
+    ) -> CaseResult<Process> {
+        let cpu_time = limits.cpu_time;
+
+        let cgroup = self
+            .sandbox
+            .cgroup()
+            .map_err(|e| CaseError::Judge(format!("Couldn't create cgroup: {e:?}")))?;
+
+        // A cgroup caps the memory of everything the program starts, otherwise each process is capped on its own.
+        // `RLIMIT_DATA` is used instead of `RLIMIT_AS` as runtimes like the JVM reserve far more address space
+        // than they ever use
+        let data_limit = match &cgroup {
+            Some(cgroup) => {
+                cgroup.limit_memory(limits.memory).map_err(|e| {
+                    CaseError::Judge(format!("Couldn't limit cgroup memory: {e:?}"))
+                })?;
+                None
+            }
+            None => Some(limits.memory),
+        };
+
+        let mut cmd = self
+            .sandbox
+            .command(&self.temp_path, script, cgroup.as_ref());
 
-        cmd.arg("-c")
-            .arg(&self.run_cmd)
-            .current_dir(&self.temp_path)
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped());
+        cmd.stdin(stdin).stdout(stdout).stderr(Stdio::piped());
 
-        let mut child = cmd
+        // SAFETY: setrlimit is async-signal-safe, and nothing is allocated in the closure
+        unsafe {
+            cmd.pre_exec(move || {
+                // SIGXCPU is sent at the soft limit, SIGKILL at the hard limit if the program ignores it
+                let limit = libc::rlimit {
+                    rlim_cur: cpu_time,
+                    rlim_max: cpu_time + 1,
+                };
+                if libc::setrlimit(libc::RLIMIT_CPU, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                if let Some(data_limit) = data_limit {
+                    let limit = libc::rlimit {
+                        rlim_cur: data_limit,
+                        rlim_max: data_limit,
+                    };
+                    if libc::setrlimit(libc::RLIMIT_DATA, &limit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        let child = cmd
             .spawn()
             .map_err(|e| CaseError::Judge(format!("Couldn't spawn process: {e:?}")))?;
+        Ok(Process { child, cgroup })
+    }
 
-        let stdin = child
+    /// Run `script` in this runner's directory with its limits applied, passing `input` in stdin
+    async fn execute(&self, script: &str, input: &str) -> Measured<ProcessOutput> {
+        let limits = self.run_limits();
+        match self.spawn(script, Stdio::piped(), Stdio::piped(), limits) {
+            Ok(process) => self.supervise(process, input, limits).await,
+            Err(e) => (Err(e), None),
+        }
+    }
+
+    /// Wait for a spawned process to exit while enforcing the given limits.
+    /// `input` is written to stdin and stdout is collected, but only if they were piped.
+    async fn supervise(
+        &self,
+        mut process: Process,
+        input: &str,
+        limits: Limits,
+    ) -> Measured<ProcessOutput> {
+        let cpu_time = limits.cpu_time;
+        let wall_time = Duration::from_secs(cpu_time * WALL_TIME_MULTIPLIER);
+
+        let pid = process.pid();
+
+        let stdin = process
+            .child
             .stdin
-            .as_mut()
-            .ok_or(CaseError::Judge("Couldn't open stdin".to_string()))?;
+            .take()
+            .map(|s| {
+                tokio::process::ChildStdin::from_std(s)
+                    .map_err(|e| CaseError::Judge(format!("Couldn't open stdin: {e:?}")))
+            })
+            .transpose();
+        let stdout = process
+            .child
+            .stdout
+            .take()
+            .map(|s| {
+                tokio::process::ChildStdout::from_std(s)
+                    .map_err(|e| CaseError::Judge(format!("Couldn't open stdout: {e:?}")))
+            })
+            .transpose();
+        let stderr = process
+            .child
+            .stderr
+            .take()
+            .ok_or(CaseError::Judge("Couldn't open stderr".to_string()))
+            .and_then(|s| {
+                tokio::process::ChildStderr::from_std(s)
+                    .map_err(|e| CaseError::Judge(format!("Couldn't open stderr: {e:?}")))
+            });
 
-        stdin
-            .write_all(input.as_bytes())
-            .await
-            .map_err(|e| CaseError::Judge(format!("Couldn't write to stdin: {e:?}")))?;
+        let (stdin, stdout, stderr) = match (stdin, stdout, stderr) {
+            (Ok(stdin), Ok(stdout), Ok(stderr)) => (stdin, stdout, stderr),
+            (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+                process.kill();
+                wait_for_exit(pid).ok();
+                return (Err(e), None);
+            }
+        };
 
-        let output = child
-            .wait_with_output()
-            .await
-            .map_err(|e| CaseError::Judge(format!("Couldn't get output: {e:?}")))?;
+        let input = input.to_string();
+        // Writing is done in a separate task so a program that doesn't read its input can't block us,
+        // errors are ignored as the program is free to exit before reading everything
+        let stdin_task = tokio::spawn(async move {
+            if let Some(mut stdin) = stdin {
+                stdin.write_all(input.as_bytes()).await.ok();
+            }
+        });
+        let max_output = limits.output;
+        // Programs printing too much to stdout are killed, stderr is just truncated
+        let mut stdout_task = tokio::spawn(async move {
+            if let Some(mut stdout) = stdout {
+                let res = read_limited(&mut stdout, max_output).await;
+                if matches!(res, Ok((_, true))) {
+                    kill_group(pid);
+                }
+                res
+            } else {
+                Ok((String::new(), false))
+            }
+        });
+        let mut stderr_task = tokio::spawn(async move {
+            let mut stderr = stderr;
+            let (err, exceeded) = read_limited(&mut stderr, max_output).await?;
+            if exceeded {
+                tokio::io::copy(&mut stderr, &mut tokio::io::sink()).await?;
+            }
+            std::io::Result::Ok(err)
+        });
 
-        // Sleep for a bit for pizzaz
-        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        let started = Instant::now();
+        let mut wait_task = tokio::task::spawn_blocking(move || wait_for_exit(pid));
 
-        if output.status.success() {
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+        let deadline = tokio::time::sleep(wall_time);
+        tokio::pin!(deadline);
+        let cancelled = cancelled(self.cancel_rx.clone());
+        tokio::pin!(cancelled);
+        let mut memory_interval = tokio::time::interval(MEMORY_POLL_INTERVAL);
+        let mut polled_memory = 0;
+        let mut limit_hit = None;
+
+        let exit_info = loop {
+            tokio::select! {
+                res = &mut wait_task => break res,
+                _ = &mut deadline, if limit_hit.is_none() => {
+                    process.kill();
+                    limit_hit = Some(CaseError::TimeLimitExceeded);
+                }
+                _ = &mut cancelled, if limit_hit.is_none() => {
+                    process.kill();
+                    limit_hit = Some(CaseError::Cancelled);
+                }
+                _ = memory_interval.tick(), if process.cgroup.is_some() => {
+                    let current = process.cgroup.as_ref().and_then(Cgroup::current_memory);
+                    polled_memory = polled_memory.max(current.unwrap_or(0));
+                }
+            }
+        };
+
+        let elapsed = started.elapsed();
+
+        // Anything the program left running in the background is killed here, this also
+        // ensures the output pipes are closed unless something escaped
+        process.kill();
+        stdin_task.abort();
+
+        let mut exit_info = match exit_info {
+            Ok(Ok(exit_info)) => exit_info,
+            Ok(Err(e)) => {
+                return (
+                    Err(CaseError::Judge(format!(
+                        "Couldn't wait for process: {e:?}"
+                    ))),
+                    None,
+                )
+            }
+            Err(e) => {
+                return (
+                    Err(CaseError::Judge(format!(
+                        "Couldn't wait for process: {e:?}"
+                    ))),
+                    None,
+                )
+            }
+        };
+
+        exit_info.status = self.sandbox.exit_status(exit_info.status);
+
+        let (oom_killed, group_memory) = match &process.cgroup {
+            Some(cgroup) => (
+                cgroup.oom_killed(),
+                cgroup.peak_memory().unwrap_or(polled_memory),
+            ),
+            None => (false, 0),
+        };
+        let peak_memory = exit_info.max_memory.max(group_memory);
+
+        let usage = ResourceUsage {
+            cpu_time_ms: exit_info.cpu_time.as_millis() as i64,
+            wall_time_ms: elapsed.as_millis() as i64,
+            memory_kb: (peak_memory / 1024) as i64,
+        };
+
+        let output = tokio::time::timeout(OUTPUT_DRAIN_TIMEOUT, async {
+            let (stdout, output_exceeded) = (&mut stdout_task)
+                .await
+                .map_err(|e| CaseError::Judge(format!("Couldn't get output: {e:?}")))?
+                .map_err(|e| CaseError::Judge(format!("Couldn't get output: {e:?}")))?;
+            let stderr = (&mut stderr_task)
+                .await
+                .map_err(|e| CaseError::Judge(format!("Couldn't get output: {e:?}")))?
+                .map_err(|e| CaseError::Judge(format!("Couldn't get output: {e:?}")))?;
+            CaseResult::Ok((stdout, output_exceeded, stderr))
+        })
+        .await;
+
+        if let Some(cgroup) = process.cgroup.take() {
+            cgroup.remove().await;
+        }
+
+        let (stdout, output_exceeded, stderr) = match output {
+            Ok(Ok(output)) => output,
+            Ok(Err(e)) => return (Err(e), Some(usage)),
+            Err(_) => {
+                // Something the program started is still holding its output open
+                stdout_task.abort();
+                stderr_task.abort();
+                let why = limit_hit.unwrap_or(CaseError::TimeLimitExceeded);
+                return (Err(why), Some(usage));
+            }
+        };
+
+        let hit_cpu_limit = exit_info.cpu_time >= Duration::from_secs(cpu_time)
+            || exit_info.status.signal() == Some(libc::SIGXCPU)
+            || exit_info.status.code() == Some(128 + libc::SIGXCPU);
+
+        // Only a cgroup can tell us a program was stopped for using too much memory. Without one the
+        // allocation that went over just fails, and whatever the program does about that is its own error.
+        let hit_memory_limit = oom_killed;
+
+        let res = if let Some(why) = limit_hit {
+            Err(why)
+        } else if output_exceeded {
+            Err(CaseError::OutputLimitExceeded)
+        } else if hit_memory_limit {
+            Err(CaseError::MemoryLimitExceeded)
+        } else if hit_cpu_limit {
+            Err(CaseError::TimeLimitExceeded)
+        } else {
+            Ok(ProcessOutput {
+                status: exit_info.status,
+                stdout,
+                stderr,
+            })
+        };
+        (res, Some(usage))
+    }
+
+    pub async fn run_cmd(&self, input: &str) -> Measured<String> {
+        let (output, usage) = self.execute(&self.run_cmd, input).await;
+
+        let res = output.and_then(|output| {
+            if output.status.success() {
+                Ok(output.stdout)
+            } else {
+                Err(self.runtime_error(&output))
+            }
+        });
+        (res, usage)
+    }
+
+    /// Hide where the program is on the server in output shown to users
+    fn sanitize(&self, output: &str) -> String {
+        let path_str = self
+            .temp_path
+            .join(&self.file_name)
+            .to_string_lossy()
+            .to_string();
+        let dir_str = self.temp_path.to_string_lossy().to_string();
+        output
+            .replace(&path_str, "<your program>")
+            .replace(&dir_str, ".")
+    }
+
+    fn runtime_error(&self, output: &ProcessOutput) -> CaseError {
+        let std_err = self.sanitize(&output.stderr);
+        let reason = match output.status.signal() {
+            Some(signal) => format!("was killed by signal {signal}"),
+            None => format!("exited with error {}", output.status.code().unwrap_or(-1)),
+        };
+        error!("Process {reason}:\n\n {std_err}");
+        CaseError::Runtime {
+            message: format!("Process {reason}:\n\n {std_err}"),
+            code: output.status.code(),
+            signal: output.status.signal(),
+        }
+    }
+
+    /// Run this program as a checker for a case. It's passed the paths to the case's input, the expected answer,
+    /// and the contestant's output as arguments. Exiting with 0 accepts the output, 1 or 2 rejects it,
+    /// and anything else means the checker itself failed. Whatever the checker prints is used as the message.
+    pub async fn run_checker(&self, input: &str, answer: &str, output: &str) -> CaseResult {
+        for (name, contents) in [
+            ("input.txt", input),
+            ("answer.txt", answer),
+            ("output.txt", output),
+        ] {
+            tokio::fs::write(self.temp_path.join(name), contents.as_bytes())
+                .await
+                .map_err(|e| CaseError::Judge(format!("Couldn't write checker file: {e:?}")))?;
+        }
+
+        let script = format!("{} input.txt answer.txt output.txt", self.run_cmd);
+
+        let (res, _) = self.execute(&script, "").await;
+        let res = res.map_err(|e| match e {
+            CaseError::Judge(_) | CaseError::Cancelled => e,
+            e => CaseError::Judge(format!("Checker failed: {e:?}")),
+        })?;
+
+        let message = if res.stdout.trim().is_empty() {
+            res.stderr.trim()
         } else {
-            let path_str = self
-                .temp_path
-                .join(&self.file_name)
-                .to_string_lossy()
-                .to_string();
-            let std_err = String::from_utf8_lossy(&output.stderr)
-                .to_string()
-                .replace(&path_str, "<your program>");
-            let code = output.status.code().unwrap_or(-1);
-            error!("Process exited with error {code}:\n\n {std_err}");
-            Err(CaseError::Runtime(format!(
-                "Process exited with error {code}:\n\n {std_err}"
-            )))
-        }
-    }
-
-    pub async fn run_case(&self, case: &TestCase) -> CaseResult<String> {
-        let output = self.run_cmd(&case.stdin).await?;
-
-        let res = case.check_output(&output, &case.expected_pattern);
-        res.map_err(CaseError::Judge).and_then(
-            |b| {
-                if b {
-                    Ok(output)
-                } else {
-                    Err(CaseError::Logic)
+            res.stdout.trim()
+        };
+
+        match res.status.code() {
+            Some(0) => Ok(()),
+            Some(1) | Some(2) => Err(CaseError::Logic(
+                (!message.is_empty()).then(|| message.to_string()),
+            )),
+            code => Err(CaseError::Judge(format!(
+                "Checker exited with {code:?}: {message}"
+            ))),
+        }
+    }
+
+    /// Start this program and an interactor with their stdin and stdout connected to each other
+    async fn start_interactive(
+        &self,
+        case: &TestCase,
+        interactor: &Runner,
+    ) -> CaseResult<(Process, Process)> {
+        for (name, contents) in [
+            ("input.txt", &case.stdin),
+            ("answer.txt", &case.expected_pattern),
+        ] {
+            tokio::fs::write(interactor.temp_path.join(name), contents.as_bytes())
+                .await
+                .map_err(|e| CaseError::Judge(format!("Couldn't write interactor file: {e:?}")))?;
+        }
+
+        let mut program = self.spawn(
+            &self.run_cmd,
+            Stdio::piped(),
+            Stdio::piped(),
+            self.run_limits(),
+        )?;
+
+        let (to_program, from_program) =
+            match (program.child.stdin.take(), program.child.stdout.take()) {
+                (Some(stdin), Some(stdout)) => (stdin, stdout),
+                _ => {
+                    program.kill();
+                    wait_for_exit(program.pid()).ok();
+                    return Err(CaseError::Judge("Couldn't open program pipes".to_string()));
                 }
-            },
-        )
+            };
+
+        let script = format!("{} input.txt answer.txt", interactor.run_cmd);
+        match interactor.spawn(
+            &script,
+            Stdio::from(from_program),
+            Stdio::from(to_program),
+            interactor.run_limits(),
+        ) {
+            Ok(process) => Ok((program, process)),
+            Err(e) => {
+                program.kill();
+                wait_for_exit(program.pid()).ok();
+                Err(e)
+            }
+        }
+    }
+
+    /// Run this program against an interactor for a case, the program's stdout is piped into the interactor's stdin and
+    /// the interactor's stdout into the program's stdin. The interactor is passed paths to the case's input and expected
+    /// answer as arguments, and gives the verdict the same way a checker does, except its message is read from stderr.
+    pub async fn run_interactive(&self, case: &TestCase, interactor: &Runner) -> Measured<String> {
+        let (program, interactor_process) = match self.start_interactive(case, interactor).await {
+            Ok(processes) => processes,
+            Err(e) => return (Err(e), None),
+        };
+
+        let ((program_res, usage), (interactor_res, _)) = tokio::join!(
+            self.supervise(program, "", self.run_limits()),
+            interactor.supervise(interactor_process, "", interactor.run_limits())
+        );
+
+        (self.interactive_verdict(program_res, interactor_res), usage)
+    }
+
+    fn interactive_verdict(
+        &self,
+        program_res: CaseResult<ProcessOutput>,
+        interactor_res: CaseResult<ProcessOutput>,
+    ) -> CaseResult<String> {
+        let program_output = program_res?;
+
+        let interactor_output = interactor_res.map_err(|e| match e {
+            CaseError::Judge(_) | CaseError::Cancelled => e,
+            e => CaseError::Judge(format!("Interactor failed: {e:?}")),
+        })?;
+
+        let message = interactor_output.stderr.trim();
+
+        // The interactor was killed trying to write to the program after it exited
+        let broken_pipe = interactor_output.status.signal() == Some(libc::SIGPIPE);
+
+        match interactor_output.status.code() {
+            _ if broken_pipe && !program_output.status.success() => {
+                Err(self.runtime_error(&program_output))
+            }
+            _ if broken_pipe => Err(CaseError::Logic(None)),
+            Some(0) if program_output.status.success() => Ok(String::new()),
+            Some(0) => Err(self.runtime_error(&program_output)),
+            // The interactor's verdict comes before runtime errors, as the program may have crashed
+            // writing to the interactor after it exited early
+            Some(1) | Some(2) => Err(CaseError::Logic(
+                (!message.is_empty()).then(|| message.to_string()),
+            )),
+            code => Err(CaseError::Judge(format!(
+                "Interactor exited with {code:?}: {message}"
+            ))),
+        }
+    }
+
+    pub async fn run_case(&self, case: &TestCase, checker: Option<&Runner>) -> Measured<String> {
+        let (output, usage) = self.run_cmd(&case.stdin).await;
+        let output = match output {
+            Ok(output) => output,
+            Err(e) => return (Err(e), usage),
+        };
+
+        let res = if let Some(checker) = checker {
+            checker
+                .run_checker(&case.stdin, &case.expected_pattern, &output)
+                .await
+                .map(|_| output)
+        } else {
+            case.check_output(&output, &case.expected_pattern)
+                .map_err(CaseError::Judge)
+                .and_then(|b| {
+                    if b {
+                        Ok(output)
+                    } else {
+                        Err(CaseError::Logic(None))
+                    }
+                })
+        };
+        (res, usage)
     }
 }
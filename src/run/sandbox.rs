@@ -0,0 +1,185 @@
+use std::{
+    os::unix::process::{CommandExt, ExitStatusExt},
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+use serde::Deserialize;
+
+use super::cgroup::Cgroup;
+
+/// Environment variables passed through to programs, everything else is cleared so secrets
+/// in the server's environment (OAuth credentials, etc.) can't be read
+const ALLOWED_ENV: [&str; 3] = ["PATH", "HOME", "LANG"];
+
+fn default_bwrap_path() -> String {
+    "bwrap".to_string()
+}
+
+fn default_file_size_limit() -> u64 {
+    64
+}
+
+#[derive(Deserialize, Clone, Default)]
+#[serde(crate = "rocket::serde", tag = "type", rename_all = "snake_case")]
+/// How to isolate submitted programs (and their compilers) from the rest of the system
+pub enum SandboxKind {
+    #[default]
+    /// Run programs directly, they will have the same access as the server.
+    /// Only use this for development!
+    None,
+    /// Run programs inside [bubblewrap](https://github.com/containers/bubblewrap).
+    /// Programs get a read-only view of the root filesystem, a private `/tmp`, and their own
+    /// user, PID, IPC, and network namespaces (meaning no network access)
+    Bubblewrap {
+        #[serde(default = "default_bwrap_path")]
+        /// Path to the `bwrap` binary
+        path: String,
+        #[serde(default)]
+        /// Extra paths to hide from programs, the directory the server is running in is always hidden
+        hide_paths: Vec<PathBuf>,
+    },
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(crate = "rocket::serde")]
+pub struct SandboxConfig {
+    #[serde(flatten)]
+    pub kind: SandboxKind,
+    /// User ID to run programs as, requires the server to be running as root
+    pub uid: Option<u32>,
+    /// Group ID to run programs as, requires the server to be running as root
+    pub gid: Option<u32>,
+    /// A cgroup v2 directory the server can create groups in, each process is run in its own group
+    /// so everything it starts can be killed, even if it leaves its process group, and memory limits
+    /// apply to all of it together. The server itself must not be in this cgroup, and the memory
+    /// controller must be enabled in its `cgroup.subtree_control`.
+    pub cgroup: Option<PathBuf>,
+    #[serde(default = "default_file_size_limit")]
+    /// Max size (in megabytes) of any file a program writes, this is also the size of the private `/tmp`
+    /// when using bubblewrap, as files there are stored in memory the program's limit doesn't see
+    pub file_size_limit: u64,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            kind: SandboxKind::default(),
+            uid: None,
+            gid: None,
+            cgroup: None,
+            file_size_limit: default_file_size_limit(),
+        }
+    }
+}
+
+impl SandboxConfig {
+    /// The user and group programs should own their working directory as, if they run as a different user
+    pub fn owner(&self) -> Option<(Option<u32>, Option<u32>)> {
+        if self.uid.is_some() || self.gid.is_some() {
+            Some((self.uid, self.gid))
+        } else {
+            None
+        }
+    }
+
+    /// Get how the program itself exited from the status of its sandbox. Bubblewrap exits with 128 + N
+    /// when the program is killed by signal N, so that's turned back into the signal.
+    pub fn exit_status(&self, status: ExitStatus) -> ExitStatus {
+        // Linux signal numbers go up to 64
+        match (&self.kind, status.code().map(|c| c - 128)) {
+            (SandboxKind::Bubblewrap { .. }, Some(signal)) if (1..=64).contains(&signal) => {
+                // A raw wait status with only a signal number means the process was killed by that signal
+                ExitStatus::from_raw(signal)
+            }
+            _ => status,
+        }
+    }
+
+    /// Create a cgroup for a process to run in, if this config has one
+    pub fn cgroup(&self) -> std::io::Result<Option<Cgroup>> {
+        self.cgroup.as_deref().map(Cgroup::create).transpose()
+    }
+
+    /// Create a command that will run `script` with bash inside `dir`, isolated according to this config.
+    /// The process is started in a new session, so its process group ID is the same as its PID.
+    /// If a cgroup is given the process joins it before anything else happens.
+    pub fn command(&self, dir: &Path, script: &str, cgroup: Option<&Cgroup>) -> Command {
+        let mut cmd = match &self.kind {
+            SandboxKind::None => {
+                let mut cmd = Command::new("bash");
+                cmd.arg("-c").arg(script);
+                cmd
+            }
+            SandboxKind::Bubblewrap { path, hide_paths } => {
+                let tmpfs_size = (self.file_size_limit * 1024 * 1024).to_string();
+                let mut cmd = Command::new(path);
+                cmd.args(["--ro-bind", "/", "/"])
+                    .args(["--dev", "/dev"])
+                    .args(["--proc", "/proc"])
+                    .args(["--size", &tmpfs_size, "--tmpfs", "/tmp"]);
+                let server_dir = std::env::current_dir().ok();
+                for hidden in server_dir.iter().chain(hide_paths.iter()) {
+                    cmd.args(["--size", &tmpfs_size, "--tmpfs"]).arg(hidden);
+                }
+                cmd.arg("--bind")
+                    .arg(dir)
+                    .arg(dir)
+                    .arg("--chdir")
+                    .arg(dir)
+                    .args(["--unshare-all", "--die-with-parent", "--"])
+                    .args(["bash", "-c", script]);
+                cmd
+            }
+        };
+
+        cmd.current_dir(dir).env_clear().envs(
+            ALLOWED_ENV
+                .iter()
+                .filter_map(|k| std::env::var_os(k).map(|v| (k, v))),
+        );
+
+        let (uid, gid) = (self.uid, self.gid);
+        let file_size = self.file_size_limit * 1024 * 1024;
+        let cgroup_procs = cgroup.map(Cgroup::procs_fd);
+
+        // SAFETY: write, setsid, setrlimit, setgroups, setgid, and setuid are all async-signal-safe and nothing is allocated.
+        // The cgroup's file descriptor is kept open by the caller until the process is spawned.
+        unsafe {
+            cmd.pre_exec(move || {
+                // This has to happen while we still have the server's permissions
+                if let Some(fd) = cgroup_procs {
+                    if libc::write(fd, b"0".as_ptr().cast(), 1) != 1 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                // A new session detaches the program from any terminal the server may have
+                if libc::setsid() == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // Stops programs from filling the disk through their working directory
+                let limit = libc::rlimit {
+                    rlim_cur: file_size,
+                    rlim_max: file_size,
+                };
+                if libc::setrlimit(libc::RLIMIT_FSIZE, &limit) != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // Group has to be dropped first, as we can't change it after dropping root
+                if let Some(gid) = gid {
+                    if libc::setgroups(0, std::ptr::null()) != 0 || libc::setgid(gid) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                if let Some(uid) = uid {
+                    if libc::setuid(uid) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+
+        cmd
+    }
+}
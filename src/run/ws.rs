@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use log::error;
 use rocket::{
     futures::{SinkExt, StreamExt},
@@ -11,12 +13,13 @@ use tokio::select;
 
 use crate::{
     auth::users::User,
+    contests::ContestProblem,
     db::DbConnection,
     problems::{Problem, TestCase},
-    run::job::{JobOperation, JobRequest},
+    run::job::{CheckerProgram, JobOperation, JobRequest},
 };
 
-use super::{JobState, JobStateReceiver, ManagerHandle};
+use super::{CodeInfo, JobState, JobStateReceiver, ManagerHandle};
 
 #[derive(Responder)]
 pub enum WsHttpResponse {
@@ -32,27 +35,18 @@ enum WebSocketRequest {
         program: String,
         language: String,
     },
+    /// Judge against only the sample cases, without saving the result
+    Samples {
+        program: String,
+        language: String,
+    },
     Test {
         program: String,
         language: String,
         input: String,
     },
-}
-
-impl WebSocketRequest {
-    pub fn program(&self) -> &str {
-        match self {
-            Self::Judge { program, .. } => program,
-            Self::Test { program, .. } => program,
-        }
-    }
-
-    pub fn language(&self) -> &str {
-        match self {
-            Self::Judge { language, .. } => language,
-            Self::Test { language, .. } => language,
-        }
-    }
+    /// Stop the user's current job for this problem
+    Cancel,
 }
 
 // Keep in sync with TypeScript type
@@ -62,6 +56,7 @@ enum WebSocketMessage {
     StateUpdate { state: JobState },
     RunStarted,
     RunDenied { reason: String },
+    CancelDenied { reason: String },
     Invalid { error: String },
 }
 
@@ -69,17 +64,72 @@ enum LoopRes {
     Msg(WebSocketMessage),
     ChangeJobRx(JobStateReceiver),
     JobStart(JobRequest),
+    JobCancel,
     Pong(Vec<u8>),
     Break,
     NoOp,
 }
 
+/// Work out what to do for a request from the client
+fn handle_request(
+    request: WebSocketRequest,
+    problem: &Problem,
+    test_cases: &[TestCase],
+    user_id: i64,
+) -> LoopRes {
+    let judge_op = |cases| {
+        if problem.interactive {
+            JobOperation::Interactive(cases)
+        } else {
+            JobOperation::Judging(cases)
+        }
+    };
+    let (program, language, op, samples_only) = match request {
+        WebSocketRequest::Cancel => return LoopRes::JobCancel,
+        WebSocketRequest::Judge { program, language } => {
+            (program, language, judge_op(test_cases.to_vec()), false)
+        }
+        WebSocketRequest::Samples { program, language } => {
+            let samples = test_cases
+                .iter()
+                .filter(|c| c.is_sample)
+                .cloned()
+                .collect::<Vec<_>>();
+            if samples.is_empty() {
+                return LoopRes::Msg(WebSocketMessage::RunDenied {
+                    reason: "This problem has no samples".to_string(),
+                });
+            }
+            (program, language, judge_op(samples), true)
+        }
+        WebSocketRequest::Test {
+            program,
+            language,
+            input,
+        } => (program, language, JobOperation::Testing(input), false),
+    };
+    LoopRes::JobStart(JobRequest {
+        user_id,
+        problem_id: problem.id,
+        program,
+        language,
+        cpu_time: problem.cpu_time,
+        memory_limit: problem.memory_limit,
+        checker: CheckerProgram::for_problem(problem),
+        run_all_cases: problem.run_all_cases,
+        samples_only,
+        rejudge_run_id: None,
+        op,
+    })
+}
+
 async fn websocket_loop(
     mut stream: DuplexStream,
     manager: ManagerHandle,
     problem: Problem,
     test_cases: Vec<TestCase>,
     user_id: i64,
+    pacing: Duration,
 ) {
     let _manager = manager.lock().await;
     let mut started_rx = _manager.subscribe();
@@ -87,7 +137,7 @@ async fn websocket_loop(
     let state_rx = _manager.get_handle(user_id, problem.id).await;
     drop(_manager);
     // Fake receiver to start the loop, will be replaced by the real one
-    let (_, fake_rx) = tokio::sync::watch::channel(JobState::new_judging(0));
+    let (_, fake_rx) = tokio::sync::watch::channel(JobState::new_judging(0, false));
 
     let mut state_msg = None;
 
@@ -110,6 +160,10 @@ async fn websocket_loop(
         }
     }
 
+    // State updates are held back so they're sent at most once every `pacing`, only the latest is sent
+    let mut state_pending = false;
+    let mut next_state_at = tokio::time::Instant::now();
+
     loop {
         let res = select! {
             Ok((user_id_incoming, problem_id, rx)) = started_rx.recv() => {
@@ -124,22 +178,9 @@ async fn websocket_loop(
                     if let Ok(client_message) = client_message {
                         match client_message {
                             rocket_ws::Message::Text(raw) => {
-                                if let Ok(request) = serde_json::from_str::<WebSocketRequest>(&raw) {
-                                    let op = match &request {
-                                        WebSocketRequest::Judge { .. } => JobOperation::Judging(test_cases.clone()),
-                                        WebSocketRequest::Test { input, .. } => JobOperation::Testing(input.to_string())
-                                    };
-                                    let job_to_start = JobRequest {
-                                        user_id,
-                                        problem_id: problem.id,
-                                        program: request.program().to_string(),
-                                        language: request.language().to_string(),
-                                        cpu_time: problem.cpu_time,
-                                        op
-                                    };
-                                    LoopRes::JobStart(job_to_start)
-                                } else {
-                                    LoopRes::Msg(WebSocketMessage::Invalid { error: "Invalid request".to_string() })
+                                match serde_json::from_str::<WebSocketRequest>(&raw) {
+                                    Ok(request) => handle_request(request, &problem, &test_cases, user_id),
+                                    Err(_) => LoopRes::Msg(WebSocketMessage::Invalid { error: "Invalid request".to_string() }),
                                 }
                             },
                             rocket_ws::Message::Ping(e) => {
@@ -159,7 +200,13 @@ async fn websocket_loop(
                     LoopRes::Break
                 }
             }
-            Ok(()) = state_rx.changed() => {
+            Ok(()) = state_rx.changed(), if !state_pending => {
+                state_pending = true;
+                LoopRes::NoOp
+            }
+            _ = tokio::time::sleep_until(next_state_at), if state_pending => {
+                state_pending = false;
+                next_state_at = tokio::time::Instant::now() + pacing;
                 let state = state_rx.borrow();
                 LoopRes::Msg(WebSocketMessage::StateUpdate { state: state.clone() })
             }
@@ -195,6 +242,22 @@ async fn websocket_loop(
                     error!("Error sending message: {:?}", e);
                 }
             }
+            LoopRes::JobCancel => {
+                let mut manager = manager.lock().await;
+                let res = manager.cancel_job(user_id, problem.id).await;
+                drop(manager);
+                // The job's state is sent as usual once it's cancelled, so only failures need a reply
+                if let Err(why) = res {
+                    let msg =
+                        serde_json::to_string(&WebSocketMessage::CancelDenied { reason: why })
+                            .map_err(|e| e.to_string())
+                            .unwrap();
+                    let res = stream.send(rocket_ws::Message::Text(msg)).await;
+                    if let Err(e) = res {
+                        error!("Error sending message: {:?}", e);
+                    }
+                }
+            }
             LoopRes::Pong(e) => {
                 let res = stream.send(rocket_ws::Message::Pong(e)).await;
                 if let Err(e) = res {
@@ -206,6 +269,7 @@ async fn websocket_loop(
             }
             LoopRes::ChangeJobRx(rx) => {
                 state_rx = rx;
+                state_pending = false;
                 let state = state_rx.borrow();
                 let msg = serde_json::to_string(&WebSocketMessage::StateUpdate {
                     state: state.clone(),
@@ -232,18 +296,24 @@ pub async fn ws_channel(
     problem_id: i64,
     user: &User,
     manager: &State<ManagerHandle>,
+    info: &State<CodeInfo>,
     mut db: DbConnection,
 ) -> WsHttpResponse {
     if let Some(problem) = Problem::get(&mut db, problem_id).await {
+        if ContestProblem::is_hidden(&mut db, &problem, Some(user)).await {
+            return WsHttpResponse::Reject(Status::NotFound);
+        }
+
         let user_id = user.id;
         let handle = (*manager).clone();
+        let pacing = Duration::from_millis(info.run_config.ui_pacing_ms);
         let cases = TestCase::get_for_problem(&mut db, problem_id)
             .await
             .unwrap_or(vec![]);
         if !cases.is_empty() {
             WsHttpResponse::Accept(ws.channel(move |stream| {
                 Box::pin(async move {
-                    websocket_loop(stream, handle, problem, cases, user_id).await;
+                    websocket_loop(stream, handle, problem, cases, user_id, pacing).await;
                     Ok(())
                 })
             }))